@@ -0,0 +1,103 @@
+//! Compares `RingSink` with the mpsc-based `ChannelSink` by the time the measurement loop spends
+//! handing samples to them, with a consumer thread draining each. Run with `cargo bench`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+use powenetics_v2::background::ChannelSink;
+use powenetics_v2::limit::SampleCountLimitSubscriber;
+use powenetics_v2::mock::MockSerialPort;
+use powenetics_v2::ring::RingSink;
+use powenetics_v2::{
+    ControlFlow, PoweneticsData, PoweneticsSubscriber, StopReason, POWENETICS_CHANNELS,
+};
+
+const SAMPLES: u64 = 200_000;
+const RING_CAPACITY: usize = 4096;
+
+/// Measures the time spent in the inner subscriber's `update`
+struct Timed<S> {
+    inner: S,
+    total: time::Duration,
+    max: time::Duration,
+}
+
+impl<S: PoweneticsSubscriber> PoweneticsSubscriber for Timed<S> {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let start = time::Instant::now();
+        let flow = self.inner.update(p);
+        let elapsed = start.elapsed();
+
+        self.total += elapsed;
+        self.max = self.max.max(elapsed);
+        flow
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.inner.end(reason, p)
+    }
+}
+
+fn run<S, C>(name: &str, sink: S, consume: C)
+where
+    S: PoweneticsSubscriber + 'static,
+    C: FnOnce(&AtomicBool) -> u64 + Send + 'static,
+{
+    let port = MockSerialPort::new().packets(
+        SAMPLES as usize,
+        [12_000; POWENETICS_CHANNELS.len()],
+        [1_000; POWENETICS_CHANNELS.len()],
+    );
+    let timed = Arc::new(Mutex::new(Timed {
+        inner: sink,
+        total: time::Duration::ZERO,
+        max: time::Duration::ZERO,
+    }));
+
+    let done = Arc::new(AtomicBool::new(false));
+    let consumer_done = done.clone();
+    let consumer = thread::spawn(move || consume(&consumer_done));
+
+    let start = time::Instant::now();
+    powenetics_v2::from_port(Box::new(port), "mock")
+        .with_subscriber(Box::new(timed.clone()))
+        .with_subscriber(Box::new(SampleCountLimitSubscriber::new(SAMPLES)))
+        .start_measurement()
+        .expect("measurement failed");
+    let elapsed = start.elapsed();
+
+    done.store(true, Ordering::Release);
+    let received = consumer.join().unwrap();
+    let timed = timed.lock().unwrap();
+
+    println!(
+        "{:<8} {:>8.1} ns/sample in sink (max {:>7.1} µs), {:>9.0} samples/s, {} of {} received",
+        name,
+        timed.total.as_nanos() as f64 / SAMPLES as f64,
+        timed.max.as_nanos() as f64 / 1e3,
+        SAMPLES as f64 / elapsed.as_secs_f64(),
+        received,
+        SAMPLES
+    );
+}
+
+fn main() {
+    let (sink, receiver) = ChannelSink::new();
+    run("mpsc", sink, move |_| receiver.iter().count() as u64);
+
+    let (sink, mut consumer) = RingSink::new(RING_CAPACITY);
+    run("ring", sink, move |done| {
+        let mut received = 0;
+
+        loop {
+            match consumer.pop() {
+                Some(_) => received += 1,
+                None if done.load(Ordering::Acquire) && consumer.is_empty() => break,
+                None => thread::yield_now(),
+            }
+        }
+
+        received
+    });
+}
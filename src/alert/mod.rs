@@ -0,0 +1,272 @@
+use crate::{
+    Channel, ChannelId, ControlFlow, PoweneticsData, PoweneticsSubscriber, POWENETICS_CHANNELS,
+};
+
+/// Fraction of the threshold used as hysteresis unless configured otherwise
+const DEFAULT_HYSTERESIS: f64 = 0.05;
+
+/// Invokes a callback whenever total system power rises above a threshold.
+///
+/// After firing, the alert is only re-armed once total power has dropped below the threshold
+/// minus the hysteresis, so a power draw hovering around the threshold does not trigger the
+/// callback on every sample. The callback returns whether the measurement should be stopped.
+pub struct PowerAlertSubscriber<F>
+where
+    F: FnMut(&PoweneticsData) -> bool + Send,
+{
+    threshold: f64,
+    hysteresis: f64,
+    on_exceed: F,
+    exceeded: bool,
+}
+
+impl<F> PowerAlertSubscriber<F>
+where
+    F: FnMut(&PoweneticsData) -> bool + Send,
+{
+    pub fn new(threshold_watts: f64, on_exceed: F) -> Self {
+        PowerAlertSubscriber {
+            threshold: threshold_watts,
+            hysteresis: threshold_watts * DEFAULT_HYSTERESIS,
+            on_exceed,
+            exceeded: false,
+        }
+    }
+
+    /// Set the hysteresis in watts
+    pub fn hysteresis(mut self, hysteresis_watts: f64) -> Self {
+        self.hysteresis = hysteresis_watts;
+        self
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.exceeded
+    }
+}
+
+impl<F> PoweneticsSubscriber for PowerAlertSubscriber<F>
+where
+    F: FnMut(&PoweneticsData) -> bool + Send,
+{
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let power = p.total_power() as f64 / 1_000_000.0;
+
+        if self.exceeded {
+            if power < self.threshold - self.hysteresis {
+                self.exceeded = false;
+            }
+
+            return Ok(ControlFlow::Continue);
+        }
+
+        if power > self.threshold {
+            self.exceeded = true;
+            return Ok((self.on_exceed)(p).into());
+        }
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Limit of a channel reading checked by `AlertSubscriber`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    OverCurrent,
+    OverPower,
+    OverVoltage,
+    UnderVoltage,
+}
+
+impl AlertKind {
+    const ALL: [AlertKind; 4] = [
+        AlertKind::OverCurrent,
+        AlertKind::OverPower,
+        AlertKind::OverVoltage,
+        AlertKind::UnderVoltage,
+    ];
+
+    /// Reading in SI units the limit applies to
+    fn reading(self, channel: &Channel) -> f64 {
+        match self {
+            AlertKind::OverCurrent => channel.current_amps(),
+            AlertKind::OverPower => channel.power_watts(),
+            AlertKind::OverVoltage | AlertKind::UnderVoltage => channel.voltage_volts(),
+        }
+    }
+
+    fn is_upper(self) -> bool {
+        self != AlertKind::UnderVoltage
+    }
+}
+
+/// Invokes a callback whenever a channel crosses one of its limits, e.g. to stop a test before
+/// hardware is damaged.
+///
+/// Limits are set per channel and kind. As with `PowerAlertSubscriber`, a limit is only re-armed
+/// once the reading has returned past it by the hysteresis, so a sustained violation fires the
+/// callback once. The callback returns whether the measurement should be stopped.
+pub struct AlertSubscriber<F>
+where
+    F: FnMut(&Channel, AlertKind) -> bool + Send,
+{
+    limits: [[Option<f64>; AlertKind::ALL.len()]; POWENETICS_CHANNELS.len()],
+    active: [[bool; AlertKind::ALL.len()]; POWENETICS_CHANNELS.len()],
+    hysteresis: f64,
+    on_alert: F,
+}
+
+impl<F> AlertSubscriber<F>
+where
+    F: FnMut(&Channel, AlertKind) -> bool + Send,
+{
+    /// Create a subscriber without any limits
+    pub fn new(on_alert: F) -> Self {
+        AlertSubscriber {
+            limits: [[None; AlertKind::ALL.len()]; POWENETICS_CHANNELS.len()],
+            active: [[false; AlertKind::ALL.len()]; POWENETICS_CHANNELS.len()],
+            hysteresis: DEFAULT_HYSTERESIS,
+            on_alert,
+        }
+    }
+
+    /// Alert when the current of `channel` rises above `amps`
+    pub fn max_current(self, channel: ChannelId, amps: f64) -> Self {
+        self.limit(channel, AlertKind::OverCurrent, amps)
+    }
+
+    /// Alert when the power of `channel` rises above `watts`
+    pub fn max_power(self, channel: ChannelId, watts: f64) -> Self {
+        self.limit(channel, AlertKind::OverPower, watts)
+    }
+
+    /// Alert when the voltage of `channel` rises above `volts`
+    pub fn max_voltage(self, channel: ChannelId, volts: f64) -> Self {
+        self.limit(channel, AlertKind::OverVoltage, volts)
+    }
+
+    /// Alert when the voltage of `channel` drops below `volts`
+    pub fn min_voltage(self, channel: ChannelId, volts: f64) -> Self {
+        self.limit(channel, AlertKind::UnderVoltage, volts)
+    }
+
+    fn limit(mut self, channel: ChannelId, kind: AlertKind, value: f64) -> Self {
+        self.limits[channel as usize][kind as usize] = Some(value);
+        self
+    }
+
+    /// Set the hysteresis as a fraction of each limit, 5% by default
+    pub fn hysteresis(mut self, fraction: f64) -> Self {
+        self.hysteresis = fraction;
+        self
+    }
+
+    /// Whether the limit of `kind` on `channel` is currently violated
+    pub fn is_active(&self, channel: ChannelId, kind: AlertKind) -> bool {
+        self.active[channel as usize][kind as usize]
+    }
+}
+
+impl<F> PoweneticsSubscriber for AlertSubscriber<F>
+where
+    F: FnMut(&Channel, AlertKind) -> bool + Send,
+{
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let mut stop = false;
+
+        for (i, channel) in p.channels().iter().enumerate() {
+            for kind in AlertKind::ALL {
+                let Some(limit) = self.limits[i][kind as usize] else {
+                    continue;
+                };
+
+                let reading = kind.reading(channel);
+                let margin = limit.abs() * self.hysteresis;
+                let active = &mut self.active[i][kind as usize];
+
+                let (violated, rearmed) = if kind.is_upper() {
+                    (reading > limit, reading < limit - margin)
+                } else {
+                    (reading < limit, reading > limit + margin)
+                };
+
+                if *active {
+                    if rearmed {
+                        *active = false;
+                    }
+                } else if violated {
+                    *active = true;
+                    stop |= (self.on_alert)(channel, kind);
+                }
+            }
+        }
+
+        Ok(stop.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::mock::MockSerialPort;
+
+    /// Readings with 12 V on every channel and `amps` drawn from the first one only
+    fn packet(port: MockSerialPort, amps: f64) -> MockSerialPort {
+        let mut currents = [0; POWENETICS_CHANNELS.len()];
+        currents[0] = (amps * 1_000.0) as u32;
+
+        port.packet([12_000; POWENETICS_CHANNELS.len()], currents)
+    }
+
+    #[test]
+    fn power_alert_fires_once_per_crossing() {
+        // 60, 120, 120, 98.4 (within the hysteresis), 120, 60 and 120 W
+        let port = [5.0, 10.0, 10.0, 8.2, 10.0, 5.0, 10.0, 5.0]
+            .into_iter()
+            .fold(MockSerialPort::new(), packet);
+        let fired = Arc::new(Mutex::new(vec![]));
+        let recorded = fired.clone();
+
+        let alert = PowerAlertSubscriber::new(100.0, move |p| {
+            recorded.lock().unwrap().push(p.sample_index());
+            p.sample_index() == 6
+        });
+        let mut p = crate::from_port(Box::new(port), "mock").with_subscriber(Box::new(alert));
+        p.start_measurement().unwrap();
+
+        assert_eq!(*fired.lock().unwrap(), [1, 6]);
+        assert_eq!(p.stop_reason(), Some(crate::StopReason::Subscriber));
+    }
+
+    #[test]
+    fn rail_falling_to_zero_is_under_voltage() {
+        let mut voltages = [12_000; POWENETICS_CHANNELS.len()];
+        let port = MockSerialPort::new().packets(2, voltages, [1_000; 13]);
+        voltages[2] = 0;
+        let port = port.packets(3, voltages, [1_000; 13]);
+
+        let fired = Arc::new(Mutex::new(vec![]));
+        let recorded = fired.clone();
+        let alert = AlertSubscriber::new(move |channel, kind| {
+            recorded.lock().unwrap().push((channel.channel_id(), kind));
+            false
+        })
+        .min_voltage(ChannelId::ALL[2], 11.0);
+        let alert = Arc::new(Mutex::new(alert));
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(alert.clone()))
+            .with_subscriber(Box::new(crate::limit::SampleCountLimitSubscriber::new(5)));
+        p.start_measurement().unwrap();
+
+        assert_eq!(
+            *fired.lock().unwrap(),
+            [(ChannelId::ALL[2], AlertKind::UnderVoltage)]
+        );
+        assert!(alert
+            .lock()
+            .unwrap()
+            .is_active(ChannelId::ALL[2], AlertKind::UnderVoltage));
+    }
+}
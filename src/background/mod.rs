@@ -0,0 +1,149 @@
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::{
+    ControlFlow, OwnedSample, Powenetics, PoweneticsData, PoweneticsError, PoweneticsSubscriber,
+    StopHandle, StopReason,
+};
+
+/// Keeps a snapshot of the most recent sample for `MeasurementThread::latest`
+struct LatestSample(Arc<Mutex<Option<OwnedSample>>>);
+
+impl PoweneticsSubscriber for LatestSample {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        // A poisoned lock only means that a reader panicked, the sample is still valid
+        let mut latest = self.0.lock().unwrap_or_else(|err| err.into_inner());
+        *latest = Some(p.snapshot());
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Sends a snapshot of every sample over an mpsc channel, e.g. to a GUI thread that cannot block
+/// in the measurement loop. Stops the measurement once the receiver has been dropped.
+///
+/// The channel is unbounded, so a receiver that falls behind accumulates samples in memory. The
+/// sender is dropped when the measurement ends, which ends iteration over the receiver; a second
+/// measurement with the same sink stops right away.
+pub struct ChannelSink {
+    sender: Option<mpsc::Sender<OwnedSample>>,
+}
+
+impl ChannelSink {
+    pub fn new() -> (ChannelSink, mpsc::Receiver<OwnedSample>) {
+        let (sender, receiver) = mpsc::channel();
+
+        (
+            ChannelSink {
+                sender: Some(sender),
+            },
+            receiver,
+        )
+    }
+}
+
+impl PoweneticsSubscriber for ChannelSink {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let sent = self
+            .sender
+            .as_ref()
+            .is_some_and(|sender| sender.send(p.snapshot()).is_ok());
+
+        Ok((!sent).into())
+    }
+
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.sender = None;
+        Ok(())
+    }
+}
+
+/// Measurement running on a background thread, see `Powenetics::start_measurement_threaded`
+pub struct MeasurementThread {
+    handle: thread::JoinHandle<Result<Powenetics, PoweneticsError>>,
+    stop: StopHandle,
+    latest: Arc<Mutex<Option<OwnedSample>>>,
+}
+
+impl MeasurementThread {
+    /// Snapshot of the most recent sample, `None` until the first sample has been processed
+    pub fn latest(&self) -> Option<OwnedSample> {
+        *self.latest.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Request the measurement to stop, see `StopHandle::stop`
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Wait for the measurement to end, returning the `Powenetics` (e.g. for its `report`) or the
+    /// error that ended the measurement. Panics of subscribers are propagated.
+    pub fn join(self) -> Result<Powenetics, PoweneticsError> {
+        match self.handle.join() {
+            Ok(result) => result,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Powenetics {
+    /// Run `start_measurement` on a background thread that owns the device and its subscribers.
+    ///
+    /// Subscribers are called on the background thread. The most recent sample is available
+    /// through `MeasurementThread::latest` without blocking the measurement for longer than
+    /// copying the sample. The subscriber providing it is removed again when the measurement
+    /// ends, so only the caller's subscribers remain on the device returned by `join`.
+    pub fn start_measurement_threaded(mut self) -> Result<MeasurementThread, PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        let latest = Arc::new(Mutex::new(None));
+        let stop = self.stop_handle();
+
+        let id = self.subscribe(Box::new(LatestSample(latest.clone())));
+
+        let handle = thread::Builder::new()
+            .name(String::from("powenetics"))
+            .spawn(move || {
+                let result = self.start_measurement();
+                self.unsubscribe(id);
+
+                result.map(|()| self)
+            })?;
+
+        Ok(MeasurementThread {
+            handle,
+            stop,
+            latest,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+    use crate::StopReason;
+
+    #[test]
+    fn latest_sample_is_unsubscribed_after_the_measurement() {
+        let port = MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(5)));
+
+        let thread = p.start_measurement_threaded().unwrap();
+        let p = thread.join().unwrap();
+
+        assert_eq!(p.subscriptions.len(), 1);
+        assert_eq!(p.stop_reason(), Some(StopReason::Subscriber));
+    }
+}
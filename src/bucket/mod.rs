@@ -0,0 +1,295 @@
+use std::time;
+
+use crate::metadata::RunMetadata;
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber, StopReason};
+
+/// Running sums over the samples of one bucket for a measured or virtual channel
+#[derive(Clone, Copy, Default)]
+struct ChannelSums {
+    voltage: u64,
+    current: u64,
+    energy: u64,
+    last_energy: Option<u64>,
+}
+
+impl ChannelSums {
+    fn add(&mut self, voltage: u16, current: u32, energy: u64) {
+        self.voltage += voltage as u64;
+        self.current += current as u64;
+
+        // Energy may have been reset in between, then all of it accrued since
+        self.energy += match self.last_energy {
+            Some(last) if energy >= last => energy - last,
+            Some(_) => energy,
+            None => 0,
+        };
+        self.last_energy = Some(energy);
+    }
+}
+
+/// Position of the most recent sample of a bucket within the measurement
+#[derive(Clone, Copy, Default)]
+struct LastSample {
+    sample_index: u64,
+    elapsed: time::Duration,
+    sequence: u16,
+    dropped_samples: u64,
+}
+
+/// Aggregates samples into buckets of fixed duration aligned to the wall clock (e.g. full
+/// minutes) and forwards one sample per bucket to the inner subscriber.
+///
+/// Forwarded samples carry the start of their bucket as timestamp, the mean voltage and current
+/// and the energy consumed within the bucket (rather than the cumulative energy) for every
+/// measured and virtual channel. Sample index, elapsed time, sequence number and dropped sample
+/// count are those of the bucket's last sample. Buckets without samples are skipped, energy consumed while no
+/// samples were received is attributed to the bucket of the next sample, including the energy
+/// since the measurement began for the first bucket. A bucket is forwarded
+/// once a sample of a later bucket arrives. The last, incomplete bucket is forwarded when the
+/// measurement ends cleanly.
+pub struct BucketSubscriber<S: PoweneticsSubscriber> {
+    inner: S,
+    bucket: time::Duration,
+    current: Option<u128>,
+    template: Option<PoweneticsData>,
+    last: LastSample,
+    samples: u64,
+    channels: Vec<ChannelSums>,
+}
+
+impl<S: PoweneticsSubscriber> BucketSubscriber<S> {
+    pub fn new(bucket: time::Duration, inner: S) -> Self {
+        assert!(!bucket.is_zero(), "bucket duration must not be zero");
+
+        BucketSubscriber {
+            inner,
+            bucket,
+            current: None,
+            template: None,
+            last: LastSample::default(),
+            samples: 0,
+            channels: vec![],
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn bucket_index(&self, time: time::SystemTime) -> u128 {
+        time.duration_since(time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos()
+            / self.bucket.as_nanos()
+    }
+
+    fn aggregate(&self, index: u128) -> Option<PoweneticsData> {
+        let mut data = self.template.clone()?;
+        let samples = self.samples.max(1);
+        let (channels, virtual_channels) = self.channels.split_at(data.channels.len());
+
+        data.last_update = time::SystemTime::UNIX_EPOCH
+            + time::Duration::from_nanos((index * self.bucket.as_nanos()) as u64);
+        data.annotations.clear();
+        data.sample_index = self.last.sample_index;
+        data.elapsed = self.last.elapsed;
+        data.sequence = self.last.sequence;
+        data.dropped_samples = self.last.dropped_samples;
+
+        for (channel, sums) in data.channels.iter_mut().zip(channels) {
+            channel.set_reading(
+                (sums.voltage / samples) as u16,
+                (sums.current / samples) as u32,
+            );
+            channel.energy = sums.energy;
+        }
+
+        for (channel, sums) in data.virtual_channels.iter_mut().zip(virtual_channels) {
+            channel.voltage = (sums.voltage / samples) as u16;
+            channel.current = (sums.current / samples) as u32;
+            channel.energy = sums.energy;
+        }
+
+        Some(data)
+    }
+
+    fn start_bucket(&mut self, index: u128) {
+        self.current = Some(index);
+        self.samples = 0;
+
+        for sums in &mut self.channels {
+            *sums = ChannelSums {
+                last_energy: sums.last_energy,
+                ..Default::default()
+            };
+        }
+    }
+}
+
+impl<S: PoweneticsSubscriber> PoweneticsSubscriber for BucketSubscriber<S> {
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.current = None;
+        self.template = None;
+        self.samples = 0;
+        self.channels = p
+            .channels
+            .iter()
+            .map(|ch| ch.energy)
+            .chain(p.virtual_channels.iter().map(|ch| ch.energy))
+            .map(|energy| ChannelSums {
+                last_energy: Some(energy),
+                ..Default::default()
+            })
+            .collect();
+        self.inner.begin(metadata, p)
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let index = self.bucket_index(p.last_update());
+        let mut result = ControlFlow::Continue;
+
+        match self.current {
+            Some(current) if current == index => {}
+            Some(current) => {
+                if let Some(data) = self.aggregate(current) {
+                    result = self.inner.update(&data)?;
+                }
+
+                self.start_bucket(index);
+            }
+            None => self.start_bucket(index),
+        }
+
+        if self.template.is_none() {
+            self.template = Some(p.clone());
+        }
+
+        self.samples += 1;
+        self.last = LastSample {
+            sample_index: p.sample_index,
+            elapsed: p.elapsed,
+            sequence: p.sequence,
+            dropped_samples: p.dropped_samples,
+        };
+
+        let channels = p
+            .channels
+            .iter()
+            .map(|ch| (ch.voltage, ch.current, ch.energy))
+            .chain(
+                p.virtual_channels
+                    .iter()
+                    .map(|ch| (ch.voltage, ch.current, ch.energy)),
+            );
+
+        for (sums, (voltage, current, energy)) in self.channels.iter_mut().zip(channels) {
+            sums.add(voltage, current, energy);
+        }
+
+        Ok(result)
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        if let Some(data) = self.current.and_then(|current| self.aggregate(current)) {
+            self.inner.update(&data)?;
+        }
+
+        self.current = None;
+        self.inner.end(reason, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+
+    type Readings = Arc<Mutex<Vec<(time::SystemTime, u16, u32, u64)>>>;
+
+    type Positions = Arc<Mutex<Vec<(u64, time::Duration, u16)>>>;
+
+    fn record(positions: &Positions) -> impl FnMut(&PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let positions = positions.clone();
+
+        move |p| {
+            positions
+                .lock()
+                .unwrap()
+                .push((p.sample_index(), p.elapsed(), p.sequence()));
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    #[test]
+    fn buckets_carry_the_position_of_their_last_sample() {
+        let port = MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let samples = Positions::default();
+        let buckets = Positions::default();
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber_fn(record(&samples))
+            .with_subscriber(Box::new(BucketSubscriber::new(
+                time::Duration::from_secs(3600),
+                crate::FnSubscriber(record(&buckets)),
+            )))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(5)));
+        p.start_measurement().unwrap();
+
+        let samples = samples.lock().unwrap();
+        let buckets = buckets.lock().unwrap();
+
+        assert_eq!(samples.len(), 5);
+        assert_eq!(buckets.last(), samples.last());
+        assert_eq!(buckets.last().unwrap().2, 5);
+    }
+
+    #[test]
+    fn aligned_buckets_average_readings_and_split_energy() {
+        let readings = Readings::default();
+        let recorded = readings.clone();
+        let mut buckets = BucketSubscriber::new(
+            time::Duration::from_secs(1),
+            crate::FnSubscriber(move |p: &PoweneticsData| {
+                let channel = &p.channels[0];
+                recorded.lock().unwrap().push((
+                    p.last_update(),
+                    channel.voltage(),
+                    channel.current(),
+                    channel.energy(),
+                ));
+                Ok(ControlFlow::Continue)
+            }),
+        );
+
+        let at = |secs: f64| time::UNIX_EPOCH + time::Duration::from_secs_f64(secs);
+        let mut data = PoweneticsData::new();
+        data.channels[0].energy = 40;
+        buckets
+            .begin(&RunMetadata::new(None, "mock", &[]), &data)
+            .unwrap();
+
+        for (secs, voltage, current, energy) in [
+            (10.0, 12_000, 1_000, 100),
+            (10.5, 12_200, 3_000, 300),
+            (11.25, 11_000, 2_000, 700),
+        ] {
+            data.last_update = at(secs);
+            data.channels[0].set_reading(voltage, current);
+            data.channels[0].energy = energy;
+            buckets.update(&data).unwrap();
+        }
+        buckets.end(StopReason::Subscriber, &data).unwrap();
+
+        // Energy before the first sample is attributed to the first bucket
+        assert_eq!(
+            *readings.lock().unwrap(),
+            [
+                (at(10.0), 12_100, 2_000, 260),
+                (at(11.0), 11_000, 2_000, 400)
+            ]
+        );
+    }
+}
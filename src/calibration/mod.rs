@@ -0,0 +1,105 @@
+use crate::{ChannelId, Powenetics, PoweneticsError};
+
+/// A sequence of per-channel calibrations that is either committed or rolled back as a whole.
+///
+/// A failed `calibrate` leaves the batch open so the caller can decide whether to continue with
+/// the remaining channels or to roll back. Rolling back (via `abort` or by dropping an uncommitted
+/// batch) issues `reset_calibration`, which restores the device's default calibration for *all*
+/// channels, including channels that were calibrated before the batch was started. The device
+/// cannot restore individual channels to their previous values.
+pub struct CalibrationBatch<'a> {
+    powenetics: &'a mut Powenetics,
+    calibrated: Vec<ChannelId>,
+    finished: bool,
+}
+
+impl<'a> CalibrationBatch<'a> {
+    pub(crate) fn new(powenetics: &'a mut Powenetics) -> Self {
+        CalibrationBatch {
+            powenetics,
+            calibrated: vec![],
+            finished: false,
+        }
+    }
+
+    pub fn calibrate(&mut self, id: ChannelId, reference: u32) -> Result<(), PoweneticsError> {
+        self.powenetics.calibrate_channel(id, reference)?;
+        self.calibrated.push(id);
+
+        Ok(())
+    }
+
+    /// Channels successfully calibrated within this batch
+    pub fn calibrated(&self) -> &[ChannelId] {
+        &self.calibrated
+    }
+
+    /// Keep all calibrations applied within this batch
+    pub fn commit(mut self) {
+        self.finished = true;
+    }
+
+    /// Roll back by resetting the calibration of all channels to the device defaults
+    pub fn abort(mut self) -> Result<(), PoweneticsError> {
+        self.finished = true;
+        self.powenetics.reset_calibration()
+    }
+}
+
+impl Drop for CalibrationBatch<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            // Errors cannot be reported from here, use abort() to handle them
+            let _ = self.powenetics.reset_calibration();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSerialPort;
+    use crate::POWENETICS_READY_MESSAGE;
+
+    const RESET_COMMAND: [u8; 4] = [0xCA, 0xAC, 0xBD, 0x00];
+
+    #[test]
+    fn failed_batch_is_rolled_back() {
+        // The device responds to a calibration only if it failed
+        let port = MockSerialPort::new()
+            .reply(&[0xCA, 1, 0x00, 0x03, 0xE8], &[0xCA, 0xAC])
+            .reply(&RESET_COMMAND, POWENETICS_READY_MESSAGE.as_bytes());
+        let written = port.written();
+        let mut p = crate::from_port(Box::new(port), "mock");
+
+        let mut batch = p.begin_calibration().unwrap();
+        batch.calibrate(ChannelId::ALL[0], 1000).unwrap();
+        assert!(matches!(
+            batch.calibrate(ChannelId::ALL[1], 1000),
+            Err(PoweneticsError::NoPowerOnChannel)
+        ));
+        assert_eq!(batch.calibrated(), [ChannelId::ALL[0]]);
+        batch.abort().unwrap();
+
+        assert!(written.lock().unwrap().ends_with(&RESET_COMMAND));
+        assert!(p.calibration.iter().all(Option::is_none));
+    }
+
+    #[test]
+    fn committed_batch_is_kept() {
+        let port = MockSerialPort::new();
+        let written = port.written();
+        let mut p = crate::from_port(Box::new(port), "mock");
+
+        let mut batch = p.begin_calibration().unwrap();
+        batch.calibrate(ChannelId::ALL[0], 1000).unwrap();
+        batch.commit();
+
+        assert!(!written.lock().unwrap().ends_with(&RESET_COMMAND));
+        assert_eq!(p.calibration[0], Some(1000));
+
+        // Dropping an uncommitted batch rolls back as well
+        drop(p.begin_calibration().unwrap());
+        assert!(written.lock().unwrap().ends_with(&RESET_COMMAND));
+    }
+}
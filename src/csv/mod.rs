@@ -1,12 +1,15 @@
 use std::fmt::Debug;
 use std::fs::File;
+use std::io::Write;
 use std::path::Path;
 use std::time;
 use std::{fs, io};
 
+use clap::ValueEnum;
 use thiserror::Error;
 
-use powenetics_v2::{Powenetics, PoweneticsData, PoweneticsSubscriber};
+use powenetics_v2::metadata::RunMetadata;
+use powenetics_v2::{ControlFlow, PoweneticsData, PoweneticsSubscriber, StopReason};
 
 #[derive(Error, Debug)]
 pub enum CsvError {
@@ -18,18 +21,106 @@ pub enum CsvError {
     CsvExists,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum TimestampFormat {
+    /// Seconds since the Unix epoch
+    Absolute,
+    /// Seconds since the first sample
+    Relative,
+    /// Both absolute and relative timestamps
+    Both,
+}
+
+#[derive(Copy, Clone)]
+pub struct CsvOptions {
+    pub timestamps: TimestampFormat,
+    /// Write run metadata as leading `#` comment lines
+    pub metadata: bool,
+    /// Write the power of every channel and the total power, following all other columns
+    pub power: bool,
+}
+
 struct CsvSubscriber {
     csv: csv::Writer<File>,
+    options: CsvOptions,
+    start: Option<time::Duration>,
+    header_written: bool,
 }
 
 impl PoweneticsSubscriber for CsvSubscriber {
-    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<bool> {
-        self.csv.write_field(format!(
-            "{:.5}",
-            p.last_update()
-                .duration_since(time::SystemTime::UNIX_EPOCH)?
-                .as_secs_f64()
-        ))?;
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        // Restarted measurements append their rows below the same header
+        if self.header_written {
+            return Ok(());
+        }
+
+        if self.options.metadata {
+            // Written past the CSV writer, which must not hold back anything buffered
+            self.csv.flush()?;
+            let mut file = self.csv.get_ref();
+
+            for (key, value) in metadata.fields() {
+                writeln!(file, "# {}: {}", key, value)?;
+            }
+        }
+
+        if self.options.timestamps != TimestampFormat::Relative {
+            self.csv.write_field("Timestamp")?;
+        }
+
+        if self.options.timestamps != TimestampFormat::Absolute {
+            self.csv.write_field("Elapsed (s)")?;
+        }
+
+        for ch in p.channels() {
+            self.csv
+                .write_field(format!("{} Voltage (mV)", ch.name()))?;
+            self.csv
+                .write_field(format!("{} Current (mA)", ch.name()))?;
+            self.csv.write_field(format!("{} Energy (nJ)", ch.name()))?;
+        }
+
+        for ch in p.virtual_channels() {
+            self.csv
+                .write_field(format!("{} Voltage (mV)", ch.name()))?;
+            self.csv
+                .write_field(format!("{} Current (mA)", ch.name()))?;
+            self.csv.write_field(format!("{} Energy (nJ)", ch.name()))?;
+        }
+
+        if self.options.power {
+            for ch in p.channels() {
+                self.csv.write_field(format!("{} Power (µW)", ch.name()))?;
+            }
+
+            self.csv.write_field("Total Power (µW)")?;
+        }
+
+        self.csv.write_record(None::<&[u8]>)?;
+        self.header_written = true;
+
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        if self.options.timestamps != TimestampFormat::Relative {
+            self.csv.write_field(format!(
+                "{:.5}",
+                p.last_update()
+                    .duration_since(time::SystemTime::UNIX_EPOCH)?
+                    .as_secs_f64()
+            ))?;
+        }
+
+        if self.options.timestamps != TimestampFormat::Absolute {
+            // Monotonic, unlike the absolute timestamps
+            let start = *self.start.get_or_insert(p.elapsed());
+
+            self.csv.write_field(format!(
+                "{:.5}",
+                p.elapsed().saturating_sub(start).as_secs_f64()
+            ))?;
+        }
 
         for ch in p.channels() {
             self.csv.write_field(format!("{}", ch.voltage()))?;
@@ -37,32 +128,173 @@ impl PoweneticsSubscriber for CsvSubscriber {
             self.csv.write_field(format!("{}", ch.energy()))?;
         }
 
+        for ch in p.virtual_channels() {
+            self.csv.write_field(format!("{}", ch.voltage()))?;
+            self.csv.write_field(format!("{}", ch.current()))?;
+            self.csv.write_field(format!("{}", ch.energy()))?;
+        }
+
+        if self.options.power {
+            for ch in p.channels() {
+                self.csv.write_field(format!("{}", ch.power()))?;
+            }
+
+            self.csv.write_field(format!("{}", p.total_power()))?;
+        }
+
         self.csv.write_record(None::<&[u8]>)?;
 
-        Ok(false)
+        Ok(ControlFlow::Continue)
+    }
+
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        // Dropping the writer flushes as well, but would swallow errors
+        self.csv.flush()?;
+        Ok(())
     }
 }
 
-pub(crate) fn subscribe_csv(p: &mut Powenetics, path: &Path) -> Result<(), CsvError> {
+pub(crate) fn create_csv(
+    path: &Path,
+    options: CsvOptions,
+) -> Result<Box<dyn PoweneticsSubscriber>, CsvError> {
     if path.try_exists()? && fs::metadata(path)?.len() != 0 {
         return Err(CsvError::CsvExists);
     }
 
-    let mut sub = CsvSubscriber {
+    let sub = CsvSubscriber {
         csv: csv::Writer::from_path(path)?,
+        options,
+        start: None,
+        header_written: false,
     };
 
-    sub.csv.write_field("Timestamp")?;
+    Ok(Box::new(sub))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::{env, process, thread};
+
+    use powenetics_v2::csv_replay::read_samples;
+    use powenetics_v2::limit::SampleCountLimitSubscriber;
+    use powenetics_v2::mock::MockSerialPort;
 
-    for ch in p.data().channels() {
-        sub.csv.write_field(format!("{} Voltage (mV)", ch.name()))?;
-        sub.csv.write_field(format!("{} Current (mA)", ch.name()))?;
-        sub.csv.write_field(format!("{} Energy (nJ)", ch.name()))?;
+    use super::*;
+
+    #[test]
+    fn relative_timestamps_start_at_zero() {
+        let path = env::temp_dir().join(format!("powenetics-{}-relative.csv", process::id()));
+        let options = CsvOptions {
+            timestamps: TimestampFormat::Relative,
+            metadata: false,
+            power: false,
+        };
+        let port = MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+
+        let mut p = powenetics_v2::from_port(Box::new(port), "mock")
+            .with_subscriber(create_csv(&path, options).unwrap())
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(5)))
+            .with_subscriber_fn(|_| {
+                thread::sleep(time::Duration::from_millis(2));
+                Ok(ControlFlow::Continue)
+            });
+        p.start_measurement().unwrap();
+
+        let mut reader = csv::Reader::from_path(&path).unwrap();
+        assert_eq!(&reader.headers().unwrap()[0], "Elapsed (s)");
+        let elapsed: Vec<f64> = reader
+            .records()
+            .map(|record| record.unwrap()[0].parse().unwrap())
+            .collect();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(elapsed.len(), 5);
+        assert_eq!(elapsed[0], 0.0);
+        assert!(elapsed.windows(2).all(|w| w[1] > w[0]));
     }
 
-    sub.csv.write_record(None::<&[u8]>)?;
+    #[test]
+    fn written_files_read_back() {
+        let path = env::temp_dir().join(format!("powenetics-{}-round-trip.csv", process::id()));
+        let options = CsvOptions {
+            timestamps: TimestampFormat::Both,
+            metadata: true,
+            power: true,
+        };
+        let port = (1..=5).fold(MockSerialPort::new(), |port, i| {
+            port.packet([12_000 + i; 13], [1_000 * i as u32; 13])
+        });
+        let samples = Arc::new(Mutex::new(vec![]));
+        let recorded = samples.clone();
 
-    p.subscribe(Box::new(sub));
+        let mut p = powenetics_v2::from_port(Box::new(port), "mock")
+            .with_subscriber(create_csv(&path, options).unwrap())
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(5)))
+            .with_subscriber_fn(move |p| {
+                recorded.lock().unwrap().push(p.snapshot());
+                thread::sleep(time::Duration::from_millis(1));
+                Ok(ControlFlow::Continue)
+            });
+        p.start_measurement().unwrap();
 
-    Ok(())
+        let read = read_samples(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let samples = samples.lock().unwrap();
+
+        assert_eq!(read.len(), samples.len());
+        for (read, sample) in read.iter().zip(samples.iter()) {
+            assert_eq!(read.voltages(), sample.voltages());
+            assert_eq!(read.currents(), sample.currents());
+            assert_eq!(read.energies(), sample.energies());
+            assert_eq!(read.sample_index(), sample.sample_index());
+
+            let offset = match read.last_update().duration_since(sample.last_update()) {
+                Ok(offset) => offset,
+                Err(err) => err.duration(),
+            };
+            assert!(offset < time::Duration::from_micros(10), "{:?}", offset);
+        }
+    }
+
+    #[test]
+    fn restarts_keep_a_single_header() {
+        let path = env::temp_dir().join(format!("powenetics-{}-restart.csv", process::id()));
+        let options = CsvOptions {
+            timestamps: TimestampFormat::Absolute,
+            metadata: true,
+            power: false,
+        };
+        let port = MockSerialPort::new()
+            .packets(2, [12_000; 13], [1_000; 13])
+            .pause()
+            .packets(2, [12_000; 13], [1_000; 13]);
+
+        let mut p = powenetics_v2::from_port(Box::new(port), "mock")
+            .with_subscriber(create_csv(&path, options).unwrap())
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(2)));
+        p.start_measurement().unwrap();
+        p.start_measurement().unwrap();
+        drop(p);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        let metadata = lines
+            .iter()
+            .take_while(|line| line.starts_with('#'))
+            .count();
+
+        assert!(metadata > 0);
+        assert_eq!(
+            lines.iter().filter(|line| line.starts_with('#')).count(),
+            metadata
+        );
+        assert!(lines[metadata].starts_with("Timestamp,"));
+        assert_eq!(lines.len(), metadata + 1 + 4);
+        assert!(lines[metadata + 1..]
+            .iter()
+            .all(|line| !line.starts_with("Timestamp")));
+    }
 }
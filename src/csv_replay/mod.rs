@@ -0,0 +1,394 @@
+use std::path::Path;
+use std::{io, time};
+
+use thiserror::Error;
+
+use crate::metadata::RunMetadata;
+use crate::{
+    ControlFlow, OwnedSample, PoweneticsData, PoweneticsSubscriber, StopReason, POWENETICS_CHANNELS,
+};
+
+const CHANNEL_COLUMNS: [&str; 3] = [" Voltage (mV)", " Current (mA)", " Energy (nJ)"];
+
+#[derive(Error, Debug)]
+pub enum CsvReplayError {
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+    #[error("CSV error")]
+    Csv(#[from] csv::Error),
+    #[error("Unexpected CSV header: {message}")]
+    Header { message: String },
+    #[error("Invalid value in CSV line {line}: {message}")]
+    Value { line: u64, message: String },
+    #[error(transparent)]
+    Subscriber(anyhow::Error),
+}
+
+/// Feed a CSV file written by the command line tool (or shaped like `CsvRowFormat`) to
+/// subscribers as if it was measured again, returning the number of samples replayed.
+///
+/// The header must start with a timestamp column (`Timestamp` or `Elapsed (s)`, the former is
+/// used where both are present) followed by the voltage, current and energy columns of all 13
+/// channels, titled with their default names as the command line tool writes them. Any further
+/// columns (virtual channels, power) are ignored.
+/// Leading `#` metadata lines are skipped. Samples keep their recorded timestamps, relative
+/// timestamps are taken as seconds since the Unix epoch. `elapsed` is derived from the
+/// timestamps, counting from the first sample.
+///
+/// Subscribers are called in the given order and may stop the replay. `ControlFlow::ResetEnergy`
+/// has no effect as the energies are taken from the recording, and unsubscribed subscribers are
+/// skipped for the remaining samples. When the replay ends, subscribers are notified with
+/// `StopReason::EndOfInput` or with the reason they stopped it for.
+pub fn replay_csv(
+    path: &Path,
+    subscribers: &mut [Box<dyn PoweneticsSubscriber>],
+) -> Result<u64, CsvReplayError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)?;
+
+    let mut data = PoweneticsData::new();
+    let first_channel = parse_header(reader.headers()?)?;
+
+    let metadata = RunMetadata::new(None, &path.to_string_lossy(), &[]);
+    for sub in subscribers.iter_mut() {
+        sub.begin(&metadata, &data)
+            .map_err(CsvReplayError::Subscriber)?;
+    }
+
+    let mut active = vec![true; subscribers.len()];
+    let mut samples = 0;
+    let mut reason = StopReason::EndOfInput;
+    let mut record = csv::StringRecord::new();
+    let mut start = None;
+
+    while reader.read_record(&mut record)? {
+        let sample = parse_record(&record, first_channel, samples, &mut start)?;
+
+        data.apply(&sample);
+        samples += 1;
+
+        let mut stop = false;
+        let mut annotations = vec![];
+
+        for (sub, active) in subscribers.iter_mut().zip(&mut active) {
+            if !*active {
+                continue;
+            }
+
+            match sub.update(&data).map_err(CsvReplayError::Subscriber)? {
+                ControlFlow::Continue | ControlFlow::ResetEnergy => {}
+                ControlFlow::Stop => stop = true,
+                ControlFlow::Annotate(text) => annotations.push(text),
+                ControlFlow::Unsubscribe => *active = false,
+            }
+        }
+
+        data.annotations = annotations;
+
+        if stop {
+            reason = StopReason::Subscriber;
+            break;
+        }
+
+        if !active.contains(&true) {
+            reason = StopReason::NoSubscribers;
+            break;
+        }
+    }
+
+    for (sub, active) in subscribers.iter_mut().zip(&active) {
+        if *active {
+            sub.end(reason, &data).map_err(CsvReplayError::Subscriber)?;
+        }
+    }
+
+    Ok(samples)
+}
+
+/// Read all samples of a CSV file written by the command line tool, see `replay_csv` for the
+/// expected layout. Columns of virtual channels are not retained.
+pub fn read_samples(path: &Path) -> Result<Vec<OwnedSample>, CsvReplayError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .from_path(path)?;
+
+    let first_channel = parse_header(reader.headers()?)?;
+
+    let mut samples = vec![];
+    let mut record = csv::StringRecord::new();
+    let mut start = None;
+
+    while reader.read_record(&mut record)? {
+        let index = samples.len() as u64;
+        samples.push(parse_record(&record, first_channel, index, &mut start)?);
+    }
+
+    Ok(samples)
+}
+
+fn parse_record(
+    record: &csv::StringRecord,
+    first_channel: usize,
+    sample_index: u64,
+    start: &mut Option<time::SystemTime>,
+) -> Result<OwnedSample, CsvReplayError> {
+    let line = record.position().map_or(0, |pos| pos.line());
+    let field = |index: usize| -> Result<&str, CsvReplayError> {
+        record.get(index).ok_or_else(|| CsvReplayError::Value {
+            line,
+            message: format!("missing column {}", index + 1),
+        })
+    };
+
+    let time = parse_value::<f64>(field(0)?, line)?;
+    let time = time::Duration::try_from_secs_f64(time).map_err(|err| CsvReplayError::Value {
+        line,
+        message: err.to_string(),
+    })?;
+
+    let last_update = time::UNIX_EPOCH + time;
+    let start = *start.get_or_insert(last_update);
+
+    let mut sample = OwnedSample {
+        last_update,
+        elapsed: last_update.duration_since(start).unwrap_or_default(),
+        sample_index,
+        ..OwnedSample::default()
+    };
+
+    for i in 0..POWENETICS_CHANNELS.len() {
+        let column = first_channel + i * CHANNEL_COLUMNS.len();
+
+        sample.voltages[i] = parse_value(field(column)?, line)?;
+        sample.currents[i] = parse_value(field(column + 1)?, line)?;
+        sample.energies[i] = parse_value(field(column + 2)?, line)?;
+    }
+
+    Ok(sample)
+}
+
+/// Check the timestamp and channel columns, returning the index of the first channel column
+fn parse_header(header: &csv::StringRecord) -> Result<usize, CsvReplayError> {
+    let first_channel = match (header.get(0), header.get(1)) {
+        (Some("Timestamp"), Some("Elapsed (s)")) => 2,
+        (Some("Timestamp"), _) | (Some("Elapsed (s)"), _) => 1,
+        (first, _) => {
+            return Err(CsvReplayError::Header {
+                message: format!(
+                    "expected \"Timestamp\" or \"Elapsed (s)\" as first column, found {:?}",
+                    first.unwrap_or_default()
+                ),
+            })
+        }
+    };
+
+    let expected = first_channel + POWENETICS_CHANNELS.len() * CHANNEL_COLUMNS.len();
+    if header.len() < expected {
+        return Err(CsvReplayError::Header {
+            message: format!(
+                "expected at least {} columns, found {}",
+                expected,
+                header.len()
+            ),
+        });
+    }
+
+    for (i, name) in POWENETICS_CHANNELS.iter().enumerate() {
+        let column = first_channel + i * CHANNEL_COLUMNS.len();
+
+        for (j, suffix) in CHANNEL_COLUMNS.iter().enumerate() {
+            let expected = format!("{}{}", name, suffix);
+            let title = &header[column + j];
+
+            if title != expected {
+                return Err(CsvReplayError::Header {
+                    message: format!(
+                        "expected column {} to be {:?}, found {:?}",
+                        column + j + 1,
+                        expected,
+                        title
+                    ),
+                });
+            }
+        }
+    }
+
+    Ok(first_channel)
+}
+
+fn parse_value<T: std::str::FromStr>(field: &str, line: u64) -> Result<T, CsvReplayError>
+where
+    T::Err: std::fmt::Display,
+{
+    field
+        .trim()
+        .parse()
+        .map_err(|err: T::Err| CsvReplayError::Value {
+            line,
+            message: format!("{:?}: {}", field, err),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::{env, fs, process};
+
+    use super::*;
+
+    struct TempCsv(std::path::PathBuf);
+
+    impl TempCsv {
+        fn new(name: &str, header: &[String], rows: &[&str]) -> Self {
+            let path = env::temp_dir().join(format!("powenetics-{}-{}", process::id(), name));
+            let mut contents = header.join(",");
+            for row in rows {
+                contents.push('\n');
+                contents.push_str(row);
+            }
+            fs::write(&path, contents).unwrap();
+
+            TempCsv(path)
+        }
+    }
+
+    impl Drop for TempCsv {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn header() -> Vec<String> {
+        let mut header = vec![String::from("Timestamp")];
+        for name in POWENETICS_CHANNELS {
+            header.extend(
+                CHANNEL_COLUMNS
+                    .iter()
+                    .map(|suffix| format!("{}{}", name, suffix)),
+            );
+        }
+
+        header
+    }
+
+    fn row(timestamp: &str, current: u32) -> String {
+        let mut row = String::from(timestamp);
+        for _ in POWENETICS_CHANNELS {
+            row.push_str(&format!(",12000,{},0", current));
+        }
+
+        row
+    }
+
+    #[test]
+    fn unexpected_columns_are_rejected() {
+        let mut missing = header();
+        missing.pop();
+        let mut unknown = header();
+        unknown[4] = String::from("CPU Voltage (mV)");
+        let mut untimed = header();
+        untimed[0] = String::from("Time");
+
+        let unknown_message = format!(
+            "expected column 5 to be \"{} Voltage (mV)\", found \"CPU Voltage (mV)\"",
+            POWENETICS_CHANNELS[1]
+        );
+
+        for (name, header, message) in [
+            (
+                "missing.csv",
+                missing,
+                "expected at least 40 columns, found 39",
+            ),
+            ("unknown.csv", unknown, unknown_message.as_str()),
+            ("untimed.csv", untimed, "as first column, found \"Time\""),
+        ] {
+            let csv = TempCsv::new(name, &header, &[]);
+
+            match read_samples(&csv.0) {
+                Err(CsvReplayError::Header { message: err }) => {
+                    assert!(err.contains(message), "{}", err)
+                }
+                result => panic!("{}: unexpected {:?}", name, result.map(|s| s.len())),
+            }
+        }
+    }
+
+    #[test]
+    fn trailing_columns_and_newline_are_tolerated() {
+        let mut header = header();
+        header.push(String::from("Total Power (µW)"));
+        let rows = [row("1.5", 100) + ",1", row("1.75", 200) + ",2\n"];
+        let csv = TempCsv::new("trailing.csv", &header, &[&rows[0], &rows[1]]);
+
+        let samples = read_samples(&csv.0).unwrap();
+
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[1].currents(), &[200; 13]);
+        assert_eq!(samples[1].elapsed(), time::Duration::from_millis(250));
+        assert_eq!(samples[1].sample_index(), 1);
+    }
+
+    /// Records the timestamps and currents seen and the stop reason, stopping at sample `stop`
+    struct Recorder {
+        stop: u64,
+        seen: Vec<(time::SystemTime, u32)>,
+        reason: Option<StopReason>,
+    }
+
+    impl PoweneticsSubscriber for Recorder {
+        fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+            self.seen.push((p.last_update(), p.channels()[0].current()));
+            Ok((p.sample_index() == self.stop).into())
+        }
+
+        fn end(&mut self, reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+            self.reason = Some(reason);
+            Ok(())
+        }
+    }
+
+    fn replay(stop: u64) -> (u64, Recorder) {
+        let rows = [row("10", 100), row("10.5", 200), row("11", 300)];
+        let csv = TempCsv::new(
+            &format!("replay-{}.csv", stop),
+            &header(),
+            &[&rows[0], &rows[1], &rows[2]],
+        );
+        let recorder = Arc::new(Mutex::new(Recorder {
+            stop,
+            seen: vec![],
+            reason: None,
+        }));
+
+        let mut subscribers: Vec<Box<dyn PoweneticsSubscriber>> = vec![Box::new(recorder.clone())];
+        let samples = replay_csv(&csv.0, &mut subscribers).unwrap();
+        drop(subscribers);
+
+        let recorder = Arc::into_inner(recorder).unwrap().into_inner().unwrap();
+        (samples, recorder)
+    }
+
+    #[test]
+    fn replay_keeps_recorded_timestamps() {
+        let (samples, recorder) = replay(u64::MAX);
+        let at = |secs: f64| time::UNIX_EPOCH + time::Duration::from_secs_f64(secs);
+
+        assert_eq!(samples, 3);
+        assert_eq!(
+            recorder.seen,
+            [(at(10.0), 100), (at(10.5), 200), (at(11.0), 300)]
+        );
+        assert_eq!(recorder.reason, Some(StopReason::EndOfInput));
+    }
+
+    #[test]
+    fn stopping_subscribers_end_the_replay_early() {
+        let (samples, recorder) = replay(1);
+
+        assert_eq!(samples, 2);
+        assert_eq!(recorder.seen.len(), 2);
+        assert_eq!(recorder.reason, Some(StopReason::Subscriber));
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::Write;
+use std::time;
+
+use crate::PoweneticsData;
+
+/// Formats samples as delimited text without depending on a CSV writer.
+///
+/// The columns match the CSV output of the command line tool with absolute timestamps: the
+/// timestamp in seconds since the Unix epoch, followed by voltage (mV), current (mA) and energy
+/// (nJ) of every channel and then of every virtual channel, and finally the power (µW) of every
+/// channel and the total power unless `power` is disabled (like `--no-power`). Fields containing
+/// the delimiter, quotes or line breaks are quoted.
+#[derive(Copy, Clone, Debug)]
+pub struct CsvRowFormat {
+    pub delimiter: char,
+    pub power: bool,
+}
+
+impl Default for CsvRowFormat {
+    fn default() -> Self {
+        CsvRowFormat {
+            delimiter: ',',
+            power: true,
+        }
+    }
+}
+
+impl CsvRowFormat {
+    /// Append the header line for samples shaped like `p`, including the line break
+    pub fn header(&self, p: &PoweneticsData, buf: &mut String) {
+        let mut fields = vec![String::from("Timestamp")];
+
+        for name in p
+            .channels()
+            .iter()
+            .map(|ch| ch.name())
+            .chain(p.virtual_channels().iter().map(|ch| ch.name()))
+        {
+            fields.push(format!("{} Voltage (mV)", name));
+            fields.push(format!("{} Current (mA)", name));
+            fields.push(format!("{} Energy (nJ)", name));
+        }
+
+        if self.power {
+            for ch in p.channels() {
+                fields.push(format!("{} Power (µW)", ch.name()));
+            }
+
+            fields.push(String::from("Total Power (µW)"));
+        }
+
+        for (i, field) in fields.iter().enumerate() {
+            if i != 0 {
+                buf.push(self.delimiter);
+            }
+
+            self.push_field(field, buf);
+        }
+
+        buf.push('\n');
+    }
+
+    /// Append the row for `p`, including the line break
+    pub fn row(&self, p: &PoweneticsData, buf: &mut String) {
+        let timestamp = p
+            .last_update()
+            .duration_since(time::SystemTime::UNIX_EPOCH)
+            .unwrap_or_default();
+
+        // Writing to a String cannot fail
+        let _ = write!(buf, "{:.5}", timestamp.as_secs_f64());
+
+        for ch in p.channels() {
+            let _ = write!(
+                buf,
+                "{d}{}{d}{}{d}{}",
+                ch.voltage(),
+                ch.current(),
+                ch.energy(),
+                d = self.delimiter
+            );
+        }
+
+        for ch in p.virtual_channels() {
+            let _ = write!(
+                buf,
+                "{d}{}{d}{}{d}{}",
+                ch.voltage(),
+                ch.current(),
+                ch.energy(),
+                d = self.delimiter
+            );
+        }
+
+        if self.power {
+            for ch in p.channels() {
+                let _ = write!(buf, "{}{}", self.delimiter, ch.power());
+            }
+
+            let _ = write!(buf, "{}{}", self.delimiter, p.total_power());
+        }
+
+        buf.push('\n');
+    }
+
+    fn push_field(&self, field: &str, buf: &mut String) {
+        if field.contains([self.delimiter, '"', '\n', '\r']) {
+            buf.push('"');
+            buf.push_str(&field.replace('"', "\"\""));
+            buf.push('"');
+        } else {
+            buf.push_str(field);
+        }
+    }
+}
+
+/// Append the comma-separated row for `p` to `buf`, see `CsvRowFormat`
+pub fn format_csv_row(p: &PoweneticsData, buf: &mut String) {
+    CsvRowFormat::default().row(p, buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::POWENETICS_CHANNELS;
+
+    #[test]
+    fn power_columns_follow_all_others() {
+        let mut data = PoweneticsData::new();
+        data.channels[0].set_reading(12_000, 2_000);
+
+        for power in [true, false] {
+            let format = CsvRowFormat {
+                power,
+                ..Default::default()
+            };
+            let mut header = String::new();
+            let mut row = String::new();
+            format.header(&data, &mut header);
+            format.row(&data, &mut row);
+
+            let header: Vec<_> = header.trim_end().split(',').collect();
+            let row: Vec<_> = row.trim_end().split(',').collect();
+            let columns = 1 + 3 * 13 + if power { 14 } else { 0 };
+
+            assert_eq!(header.len(), columns);
+            assert_eq!(row.len(), columns);
+
+            if power {
+                assert_eq!(header[40], format!("{} Power (µW)", POWENETICS_CHANNELS[0]));
+                assert_eq!(row[40], "24000000");
+                assert_eq!(header[53], "Total Power (µW)");
+                assert_eq!(row[53], "24000000");
+            }
+        }
+    }
+}
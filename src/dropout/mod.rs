@@ -0,0 +1,106 @@
+use std::{array, time};
+
+use crate::metadata::RunMetadata;
+use crate::{ChannelId, ControlFlow, PoweneticsData, PoweneticsSubscriber, POWENETICS_CHANNELS};
+
+/// A channel's voltage dropping to zero while other channels remained powered
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RailDropout {
+    pub channel: ChannelId,
+    /// Voltage of the previous sample in mV
+    pub last_voltage: u16,
+    pub time: time::SystemTime,
+}
+
+/// Detects rails dropping out mid-measurement, e.g. due to a loose connector.
+///
+/// A dropout is a channel going from a nonzero voltage to exactly 0 mV while at least one other
+/// channel still reads a nonzero voltage. Channels that read zero from the start (unconnected
+/// rails) and the whole system powering off are not reported. Every dropout is logged as a
+/// warning and recorded.
+#[derive(Default)]
+pub struct RailDropoutSubscriber {
+    last_voltages: Option<[u16; POWENETICS_CHANNELS.len()]>,
+    dropouts: Vec<RailDropout>,
+}
+
+impl RailDropoutSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Dropouts detected so far, in order of occurrence
+    pub fn dropouts(&self) -> &[RailDropout] {
+        &self.dropouts
+    }
+}
+
+impl PoweneticsSubscriber for RailDropoutSubscriber {
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.last_voltages = None;
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let voltages: [u16; POWENETICS_CHANNELS.len()] =
+            array::from_fn(|i| p.channels()[i].voltage());
+
+        if let Some(last_voltages) = self.last_voltages {
+            let powered = voltages.iter().filter(|&&v| v != 0).count();
+
+            for (i, channel) in p.channels().iter().enumerate() {
+                if last_voltages[i] == 0 || voltages[i] != 0 || powered == 0 {
+                    continue;
+                }
+
+                log::warn!(
+                    "{} dropped from {} mV to 0 mV",
+                    channel.name(),
+                    last_voltages[i]
+                );
+
+                self.dropouts.push(RailDropout {
+                    channel: channel.channel_id(),
+                    last_voltage: last_voltages[i],
+                    time: p.last_update(),
+                });
+            }
+        }
+
+        self.last_voltages = Some(voltages);
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::mock::MockSerialPort;
+
+    #[test]
+    fn rail_falling_to_zero_is_a_dropout() {
+        let mut voltages = [12_000; POWENETICS_CHANNELS.len()];
+        // An unconnected rail is never reported
+        voltages[12] = 0;
+        let port = MockSerialPort::new().packets(3, voltages, [1_000; 13]);
+        voltages[1] = 0;
+        let port = port
+            .packets(2, voltages, [1_000; 13])
+            // Powering off the whole system is no dropout either
+            .packets(2, [0; 13], [0; 13]);
+
+        let dropouts = Arc::new(Mutex::new(RailDropoutSubscriber::new()));
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(dropouts.clone()))
+            .with_subscriber(Box::new(crate::limit::SampleCountLimitSubscriber::new(7)));
+        p.start_measurement().unwrap();
+
+        let dropouts = dropouts.lock().unwrap();
+        assert_eq!(dropouts.dropouts().len(), 1);
+        assert_eq!(dropouts.dropouts()[0].channel, ChannelId::ALL[1]);
+        assert_eq!(dropouts.dropouts()[0].last_voltage, 12_000);
+    }
+}
@@ -0,0 +1,179 @@
+use std::io::{Read, Write};
+use std::{io, time};
+
+/// State of an emulated serial port needed to implement `SerialPort` with `emulated_serial_port!`.
+/// All line settings are fixed to those of the device and cannot be changed.
+pub(crate) trait EmulatedPort: Read + Write + Send {
+    fn port_name(&self) -> &'static str;
+
+    /// Number of bytes that can be read without blocking
+    fn pending(&self) -> serialport::Result<usize>;
+
+    fn read_timeout(&self) -> time::Duration;
+
+    fn set_read_timeout(&mut self, timeout: time::Duration);
+}
+
+/// Implement `SerialPort` for a type implementing `EmulatedPort`
+macro_rules! emulated_serial_port {
+    ($port:ty) => {
+        impl serialport::SerialPort for $port {
+            fn name(&self) -> Option<String> {
+                Some(String::from($crate::emulated::EmulatedPort::port_name(
+                    self,
+                )))
+            }
+
+            fn baud_rate(&self) -> serialport::Result<u32> {
+                Ok($crate::POWENETICS_BAUD_RATE)
+            }
+
+            fn data_bits(&self) -> serialport::Result<serialport::DataBits> {
+                Ok($crate::POWENETICS_DATA_BITS)
+            }
+
+            fn flow_control(&self) -> serialport::Result<serialport::FlowControl> {
+                Ok(serialport::FlowControl::None)
+            }
+
+            fn parity(&self) -> serialport::Result<serialport::Parity> {
+                Ok($crate::POWENETICS_SERIAL_PARITY)
+            }
+
+            fn stop_bits(&self) -> serialport::Result<serialport::StopBits> {
+                Ok($crate::POWENETICS_STOP_BITS)
+            }
+
+            fn timeout(&self) -> std::time::Duration {
+                $crate::emulated::EmulatedPort::read_timeout(self)
+            }
+
+            fn set_baud_rate(&mut self, _baud_rate: u32) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn set_data_bits(
+                &mut self,
+                _data_bits: serialport::DataBits,
+            ) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn set_flow_control(
+                &mut self,
+                _flow_control: serialport::FlowControl,
+            ) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn set_parity(&mut self, _parity: serialport::Parity) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn set_stop_bits(
+                &mut self,
+                _stop_bits: serialport::StopBits,
+            ) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn set_timeout(&mut self, timeout: std::time::Duration) -> serialport::Result<()> {
+                $crate::emulated::EmulatedPort::set_read_timeout(self, timeout);
+                Ok(())
+            }
+
+            fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+
+            fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+
+            fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+                Ok(false)
+            }
+
+            fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+                Ok(true)
+            }
+
+            fn bytes_to_read(&self) -> serialport::Result<u32> {
+                Ok($crate::emulated::EmulatedPort::pending(self)? as u32)
+            }
+
+            fn bytes_to_write(&self) -> serialport::Result<u32> {
+                Ok(0)
+            }
+
+            fn clear(&self, _buffer_to_clear: serialport::ClearBuffer) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn try_clone(&self) -> serialport::Result<Box<dyn serialport::SerialPort>> {
+                Err(serialport::Error::new(
+                    serialport::ErrorKind::Unknown,
+                    format!(
+                        "{} ports cannot be cloned",
+                        $crate::emulated::EmulatedPort::port_name(self)
+                    ),
+                ))
+            }
+
+            fn set_break(&self) -> serialport::Result<()> {
+                Ok(())
+            }
+
+            fn clear_break(&self) -> serialport::Result<()> {
+                Ok(())
+            }
+        }
+    };
+}
+
+pub(crate) use emulated_serial_port;
+
+/// Stand-in for a port that has been closed, failing all reads and writes
+pub(crate) struct DisconnectedPort;
+
+impl Read for DisconnectedPort {
+    fn read(&mut self, _buf: &mut [u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+}
+
+impl Write for DisconnectedPort {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::NotConnected.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EmulatedPort for DisconnectedPort {
+    fn port_name(&self) -> &'static str {
+        "disconnected"
+    }
+
+    fn pending(&self) -> serialport::Result<usize> {
+        Ok(0)
+    }
+
+    fn read_timeout(&self) -> time::Duration {
+        crate::POWENETICS_TIMEOUT
+    }
+
+    fn set_read_timeout(&mut self, _timeout: time::Duration) {}
+}
+
+emulated_serial_port!(DisconnectedPort);
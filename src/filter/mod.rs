@@ -0,0 +1,221 @@
+use std::time;
+
+use crate::metadata::RunMetadata;
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber, StopReason, POWENETICS_CHANNELS};
+
+/// Voltages and currents of the previously forwarded sample, with their checksum to skip the
+/// comparison of differing samples
+#[derive(PartialEq)]
+struct Readings {
+    checksum: u64,
+    voltages: [u16; POWENETICS_CHANNELS.len()],
+    currents: [u32; POWENETICS_CHANNELS.len()],
+}
+
+impl Readings {
+    fn of(p: &PoweneticsData) -> Self {
+        Readings {
+            checksum: p.checksum(),
+            voltages: std::array::from_fn(|i| p.channels[i].voltage),
+            currents: std::array::from_fn(|i| p.channels[i].current),
+        }
+    }
+}
+
+/// Forwards a sample to the inner subscriber only if its voltages or currents differ from the
+/// previously forwarded sample.
+///
+/// Energy keeps accruing in the measurement loop regardless, so the energy of the next forwarded
+/// sample includes the energy of all samples skipped in between.
+pub struct DedupSubscriber<S: PoweneticsSubscriber> {
+    inner: S,
+    last: Option<Readings>,
+}
+
+impl<S: PoweneticsSubscriber> DedupSubscriber<S> {
+    pub fn new(inner: S) -> Self {
+        DedupSubscriber { inner, last: None }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S: PoweneticsSubscriber> PoweneticsSubscriber for DedupSubscriber<S> {
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.last = None;
+        self.inner.begin(metadata, p)
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let readings = Readings::of(p);
+
+        if self.last.as_ref() == Some(&readings) {
+            return Ok(ControlFlow::Continue);
+        }
+
+        self.last = Some(readings);
+        self.inner.update(p)
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.inner.end(reason, p)
+    }
+}
+
+enum Interval {
+    Samples(u64),
+    Time(time::Duration),
+}
+
+/// Forwards only some samples to the inner subscriber, e.g. for an output that cannot keep up
+/// with the full sample rate. The first sample is always forwarded.
+///
+/// As with `DedupSubscriber`, energy keeps accruing in the measurement loop, so the energy of a
+/// forwarded sample includes the energy of all samples skipped before it. Skipped samples do not
+/// stop the measurement; the inner subscriber's control flow applies to forwarded samples only.
+pub struct DecimateSubscriber<S: PoweneticsSubscriber> {
+    inner: S,
+    interval: Interval,
+    skipped: u64,
+    last_forwarded: Option<time::Duration>,
+}
+
+impl<S: PoweneticsSubscriber> DecimateSubscriber<S> {
+    /// Forward every `n`th sample
+    pub fn every_n(inner: S, n: u64) -> Self {
+        assert!(n > 0, "decimation factor must not be zero");
+        Self::new(inner, Interval::Samples(n))
+    }
+
+    /// Forward the first sample received at least `period` after the previously forwarded one,
+    /// measured by `PoweneticsData::elapsed` so that system clock adjustments do not matter
+    pub fn every(inner: S, period: time::Duration) -> Self {
+        Self::new(inner, Interval::Time(period))
+    }
+
+    fn new(inner: S, interval: Interval) -> Self {
+        DecimateSubscriber {
+            inner,
+            interval,
+            skipped: 0,
+            last_forwarded: None,
+        }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn forward(&self, p: &PoweneticsData) -> bool {
+        let Some(last) = self.last_forwarded else {
+            return true;
+        };
+
+        match self.interval {
+            Interval::Samples(n) => self.skipped + 1 >= n,
+            Interval::Time(period) => p.elapsed().saturating_sub(last) >= period,
+        }
+    }
+}
+
+impl<S: PoweneticsSubscriber> PoweneticsSubscriber for DecimateSubscriber<S> {
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.skipped = 0;
+        self.last_forwarded = None;
+        self.inner.begin(metadata, p)
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        if !self.forward(p) {
+            self.skipped += 1;
+            return Ok(ControlFlow::Continue);
+        }
+
+        self.skipped = 0;
+        self.last_forwarded = Some(p.elapsed());
+        self.inner.update(p)
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.inner.end(reason, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+
+    struct Count(u64);
+
+    impl PoweneticsSubscriber for Count {
+        fn update(&mut self, _p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+            self.0 += 1;
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    #[test]
+    fn identical_samples_are_forwarded_once() {
+        let port = MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .packets(2, [12_000; 13], [2_000; 13])
+            .packets(2, [12_000; 13], [1_000; 13]);
+        let count = Arc::new(Mutex::new(Count(0)));
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(DedupSubscriber::new(count.clone())))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(7)));
+        p.start_measurement().unwrap();
+
+        assert_eq!(count.lock().unwrap().0, 3);
+    }
+
+    #[test]
+    fn any_changed_reading_is_forwarded() {
+        let mut dedup = DedupSubscriber::new(Count(0));
+        let mut data = PoweneticsData::new();
+
+        dedup.update(&data).unwrap();
+        dedup.update(&data).unwrap();
+        data.channels[12].set_reading(0, 1);
+        dedup.update(&data).unwrap();
+        data.channels[0].set_reading(1, 1);
+        dedup.update(&data).unwrap();
+        dedup.update(&data).unwrap();
+
+        assert_eq!(dedup.into_inner().0, 3);
+    }
+
+    #[test]
+    fn time_decimation_ignores_clock_steps() {
+        let mut decimate = DecimateSubscriber::every(Count(0), time::Duration::from_millis(10));
+        let mut data = PoweneticsData::new();
+        data.last_update = time::SystemTime::now();
+
+        for _ in 0..10 {
+            decimate.update(&data).unwrap();
+            data.elapsed += time::Duration::from_millis(5);
+            data.last_update -= time::Duration::from_secs(1);
+        }
+
+        assert_eq!(decimate.into_inner().0, 5);
+    }
+
+    #[test]
+    fn sample_decimation_forwards_every_nth() {
+        let mut decimate = DecimateSubscriber::every_n(Count(0), 3);
+        let data = PoweneticsData::new();
+
+        for _ in 0..10 {
+            decimate.update(&data).unwrap();
+        }
+
+        assert_eq!(decimate.into_inner().0, 4);
+    }
+}
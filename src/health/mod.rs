@@ -0,0 +1,240 @@
+use std::collections::VecDeque;
+use std::time;
+
+/// Weight of the most recent inter-packet interval in the sample rate estimate
+const SAMPLE_RATE_SMOOTHING: f64 = 0.01;
+/// Number of received packets the drop rate is computed over
+const DROP_RATE_WINDOW: usize = 1000;
+
+/// Snapshot of the link quality between host and device
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LinkHealth {
+    /// Measurement packets received and processed
+    pub packets: u64,
+    /// Packets missing according to gaps in the sequence number
+    pub dropped_packets: u64,
+    /// Packets received with the same sequence number as their predecessor
+    pub duplicate_packets: u64,
+    /// Packets rejected due to a checksum mismatch (the packet format has no checksum, always
+    /// zero)
+    pub checksum_failures: u64,
+    /// Largest number of bytes observed waiting in the input buffer after reading a packet
+    pub backlog_high_water: u32,
+    /// Number of times the serial port was reopened after an I/O error, see
+    /// `PoweneticsConfig::reconnect_attempts`
+    pub reconnects: u64,
+    /// Number of times the device reset itself mid-stream (announced by its ready message) and
+    /// the measurement was restarted
+    pub device_resets: u64,
+    /// Number of times the stream was resynchronized after a corrupted packet, see
+    /// `Powenetics::set_error_recovery`
+    pub resyncs: u64,
+    /// Number of packets received with a timestamp earlier than their predecessor's, e.g. due to
+    /// an NTP correction. No energy is accumulated over such an interval.
+    pub clock_steps_back: u64,
+    /// Current packet rate in Hz, zero until at least two packets have been received
+    pub sample_rate: f64,
+    /// Mean interval between two consecutive packets
+    pub mean_interval: time::Duration,
+    /// Standard deviation of the interval between two consecutive packets
+    pub interval_jitter: time::Duration,
+    /// Number of intervals the interval statistics are based on
+    pub intervals: u64,
+}
+
+/// Cumulative traffic on the serial port
+#[derive(Copy, Clone, Debug, Default)]
+pub struct IoStats {
+    /// Bytes read, including ready messages and command responses
+    pub bytes_read: u64,
+    /// Bytes written, i.e. commands sent to the device
+    pub bytes_written: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct LinkCounters {
+    health: LinkHealth,
+    mean_interval: Option<f64>,
+    last_packet: Option<time::SystemTime>,
+    // Running mean and sum of squared deviations of the intervals (Welford's algorithm)
+    interval_mean: f64,
+    interval_m2: f64,
+}
+
+impl LinkCounters {
+    pub(crate) fn packet(&mut self, time: time::SystemTime) {
+        self.health.packets += 1;
+
+        if let Some(interval) = self
+            .last_packet
+            .and_then(|last| time.duration_since(last).ok())
+            .map(|interval| interval.as_secs_f64())
+        {
+            let mean = match self.mean_interval {
+                Some(mean) => mean + SAMPLE_RATE_SMOOTHING * (interval - mean),
+                None => interval,
+            };
+
+            self.mean_interval = Some(mean);
+            self.health.sample_rate = if mean > 0.0 { 1.0 / mean } else { 0.0 };
+
+            self.health.intervals += 1;
+            let delta = interval - self.interval_mean;
+            self.interval_mean += delta / self.health.intervals as f64;
+            self.interval_m2 += delta * (interval - self.interval_mean);
+
+            self.health.mean_interval = time::Duration::from_secs_f64(self.interval_mean);
+            self.health.interval_jitter = time::Duration::from_secs_f64(
+                (self.interval_m2 / self.health.intervals as f64).sqrt(),
+            );
+        }
+
+        self.last_packet = Some(time);
+    }
+
+    pub(crate) fn dropped(&mut self, count: u64) {
+        self.health.dropped_packets += count;
+    }
+
+    pub(crate) fn duplicate(&mut self) {
+        self.health.duplicate_packets += 1;
+    }
+
+    pub(crate) fn device_reset(&mut self) {
+        self.health.device_resets += 1;
+    }
+
+    pub(crate) fn reconnect(&mut self) {
+        self.health.reconnects += 1;
+    }
+
+    pub(crate) fn clock_step_back(&mut self) {
+        self.health.clock_steps_back += 1;
+    }
+
+    pub(crate) fn resync(&mut self) {
+        self.health.resyncs += 1;
+    }
+
+    pub(crate) fn backlog(&mut self, bytes: u32) {
+        self.health.backlog_high_water = self.health.backlog_high_water.max(bytes);
+    }
+
+    /// Forget the packet timing, e.g. after the stream was interrupted
+    pub(crate) fn restart(&mut self) {
+        self.last_packet = None;
+    }
+
+    pub(crate) fn health(&self) -> LinkHealth {
+        self.health
+    }
+}
+
+/// Packets dropped before each of the most recently received packets
+#[derive(Default)]
+pub(crate) struct DropWindow {
+    dropped: VecDeque<u64>,
+    total: u64,
+}
+
+impl DropWindow {
+    pub(crate) fn record(&mut self, dropped: u64) {
+        if self.dropped.len() == DROP_RATE_WINDOW {
+            self.total -= self.dropped.pop_front().unwrap_or(0);
+        }
+
+        self.dropped.push_back(dropped);
+        self.total += dropped;
+    }
+
+    /// Fraction of packets dropped within the window. Until the window is full, it is treated as
+    /// padded with received packets, so a single drop at the start does not count as a high rate.
+    pub(crate) fn rate(&self) -> f64 {
+        let packets = (self.total + self.dropped.len() as u64).max(DROP_RATE_WINDOW as u64);
+        self.total as f64 / packets as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(counters: &mut LinkCounters, intervals_ms: impl IntoIterator<Item = u64>) {
+        let mut time = time::UNIX_EPOCH;
+        counters.packet(time);
+
+        for interval in intervals_ms {
+            time += time::Duration::from_millis(interval);
+            counters.packet(time);
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 1e-6,
+            "{} differs from {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn sample_rate_follows_the_intervals() {
+        let mut counters = LinkCounters::default();
+        feed(&mut counters, []);
+        assert_eq!(counters.health().sample_rate, 0.0);
+
+        let mut counters = LinkCounters::default();
+        feed(&mut counters, [10; 50]);
+        let health = counters.health();
+
+        assert_eq!(health.packets, 51);
+        assert_eq!(health.intervals, 50);
+        assert_close(health.sample_rate, 100.0);
+        assert_close(health.mean_interval.as_secs_f64(), 0.010);
+        assert_eq!(health.interval_jitter, time::Duration::ZERO);
+    }
+
+    #[test]
+    fn jitter_is_the_standard_deviation_of_the_intervals() {
+        let mut counters = LinkCounters::default();
+        feed(&mut counters, [8, 12].repeat(50));
+        let health = counters.health();
+
+        assert_eq!(health.intervals, 100);
+        assert_close(health.mean_interval.as_secs_f64(), 0.010);
+        assert_close(health.interval_jitter.as_secs_f64(), 0.002);
+    }
+
+    #[test]
+    fn restarts_skip_the_interval_across_the_gap() {
+        let mut counters = LinkCounters::default();
+        feed(&mut counters, [10; 10]);
+        counters.restart();
+        counters.packet(time::UNIX_EPOCH + time::Duration::from_secs(60));
+
+        assert_eq!(counters.health().intervals, 10);
+        assert_close(counters.health().mean_interval.as_secs_f64(), 0.010);
+    }
+
+    #[test]
+    fn drop_rate_is_padded_until_the_window_is_full() {
+        let mut window = DropWindow::default();
+        assert_eq!(window.rate(), 0.0);
+
+        window.record(0);
+        window.record(5);
+        assert_close(window.rate(), 5.0 / DROP_RATE_WINDOW as f64);
+
+        for _ in 0..DROP_RATE_WINDOW {
+            window.record(1);
+        }
+        // One dropped packet per received one
+        assert_close(window.rate(), 0.5);
+
+        for _ in 0..DROP_RATE_WINDOW {
+            window.record(0);
+        }
+        assert_eq!(window.rate(), 0.0);
+    }
+}
@@ -0,0 +1,153 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::path::Path;
+use std::time;
+
+use thiserror::Error;
+
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber, StopReason};
+
+#[derive(Error, Debug)]
+pub enum InfluxError {
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+    #[error("line protocol file already exists and is not empty")]
+    InfluxExists,
+}
+
+/// Writes every sample as InfluxDB line protocol to an `io::Write` sink, one line per channel:
+///
+/// `powenetics,channel=ATX_12V voltage=12034i,current=2041i,power=24561394i 1700000000000000000`
+///
+/// Fields are integers in mV, mA and µW, timestamps are nanoseconds since the Unix epoch taken
+/// from `last_update()`. Spaces in channel names are replaced by underscores. All lines of a
+/// sample are passed to the sink in a single write, so with `udp` each sample is sent as one
+/// datagram.
+pub struct InfluxSubscriber<W: Write + Send> {
+    writer: W,
+    measurement: String,
+    tags: String,
+    buf: String,
+}
+
+impl<W: Write + Send> InfluxSubscriber<W> {
+    pub fn new(writer: W) -> Self {
+        InfluxSubscriber {
+            writer,
+            measurement: String::from("powenetics"),
+            tags: String::new(),
+            buf: String::new(),
+        }
+    }
+
+    /// Set the measurement name, `powenetics` by default
+    pub fn measurement(mut self, name: &str) -> Self {
+        self.measurement.clear();
+        escape(&mut self.measurement, name, &[',', ' ']);
+        self
+    }
+
+    /// Add a tag to all lines, in addition to the `channel` tag
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.push(',');
+        escape(&mut self.tags, key, &[',', '=', ' ']);
+        self.tags.push('=');
+        escape(&mut self.tags, value, &[',', '=', ' ']);
+        self
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl InfluxSubscriber<UdpSink> {
+    /// Send samples to the UDP listener of an InfluxDB (or Telegraf) at `addr`
+    pub fn udp<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let addr = addr.to_socket_addrs()?.next().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, "address did not resolve")
+        })?;
+
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => (Ipv4Addr::UNSPECIFIED, 0).into(),
+            SocketAddr::V6(_) => (Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+
+        Ok(Self::new(UdpSink { socket }))
+    }
+}
+
+impl<W: Write + Send> PoweneticsSubscriber for InfluxSubscriber<W> {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let timestamp = p
+            .last_update()
+            .duration_since(time::SystemTime::UNIX_EPOCH)?
+            .as_nanos();
+
+        self.buf.clear();
+
+        for ch in p.channels() {
+            write!(self.buf, "{},channel=", self.measurement)?;
+            escape(&mut self.buf, &ch.name().replace(' ', "_"), &[',', '=']);
+            writeln!(
+                self.buf,
+                "{} voltage={}i,current={}i,power={}i {}",
+                self.tags,
+                ch.voltage(),
+                ch.current(),
+                ch.power(),
+                timestamp
+            )?;
+        }
+
+        self.writer.write_all(self.buf.as_bytes())?;
+
+        Ok(ControlFlow::Continue)
+    }
+
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Subscriber writing samples to `path` as InfluxDB line protocol, e.g. for a later import with
+/// `influx write`. Refuses to overwrite a non-empty file.
+pub fn create_influx(path: &Path) -> Result<Box<dyn PoweneticsSubscriber>, InfluxError> {
+    if path.try_exists()? && fs::metadata(path)?.len() != 0 {
+        return Err(InfluxError::InfluxExists);
+    }
+
+    Ok(Box::new(InfluxSubscriber::new(BufWriter::new(
+        File::create(path)?,
+    ))))
+}
+
+/// Connected UDP socket sending every write as one datagram
+pub struct UdpSink {
+    socket: UdpSocket,
+}
+
+impl Write for UdpSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.socket.send(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn escape(out: &mut String, s: &str, special: &[char]) {
+    for c in s.chars() {
+        if special.contains(&c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
@@ -0,0 +1,65 @@
+use std::sync::OnceLock;
+
+use powenetics_v2::StopHandle;
+
+static STOP: OnceLock<StopHandle> = OnceLock::new();
+
+/// Stop the measurement cleanly on the first Ctrl-C (or SIGTERM on Unix), so that outputs are
+/// flushed. A second Ctrl-C terminates the process as usual, e.g. if no more samples arrive.
+pub(crate) fn stop_on_interrupt(stop: StopHandle) {
+    if STOP.set(stop).is_ok() {
+        install();
+    }
+}
+
+fn request_stop() {
+    if let Some(stop) = STOP.get() {
+        stop.stop();
+    }
+}
+
+#[cfg(unix)]
+fn install() {
+    extern "C" fn handler(signal: libc::c_int) {
+        request_stop();
+
+        // Atomic stores and signal() are async-signal-safe
+        unsafe {
+            libc::signal(signal, libc::SIG_DFL);
+        }
+    }
+
+    let handler = handler as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+#[cfg(windows)]
+fn install() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+    use winapi::um::wincon::CTRL_C_EVENT;
+
+    static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+    unsafe extern "system" fn handler(ctrl_type: DWORD) -> BOOL {
+        if ctrl_type != CTRL_C_EVENT || INTERRUPTED.swap(true, Ordering::Relaxed) {
+            // Fall back to the default handler, which terminates the process
+            return FALSE;
+        }
+
+        request_stop();
+        TRUE
+    }
+
+    unsafe {
+        winapi::um::consoleapi::SetConsoleCtrlHandler(Some(handler), TRUE);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn install() {}
@@ -0,0 +1,227 @@
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::{fs, io, time};
+
+use thiserror::Error;
+
+use crate::metadata::RunMetadata;
+use crate::{
+    ControlFlow, Powenetics, PoweneticsData, PoweneticsSubscriber, StopReason, SubscriptionId,
+};
+
+#[derive(Error, Debug)]
+pub enum JsonError {
+    #[error("I/O error")]
+    Io(#[from] io::Error),
+    #[error("JSON file already exists and is not empty")]
+    JsonExists,
+}
+
+struct JsonSubscriber {
+    out: BufWriter<File>,
+    line: String,
+    metadata: bool,
+}
+
+impl PoweneticsSubscriber for JsonSubscriber {
+    fn begin(&mut self, metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        if self.metadata {
+            self.line.clear();
+            self.line.push_str("{\"metadata\":{");
+
+            for (i, (key, value)) in metadata.fields().iter().enumerate() {
+                if i != 0 {
+                    self.line.push(',');
+                }
+
+                write_string(&mut self.line, key);
+                self.line.push(':');
+                write_string(&mut self.line, value);
+            }
+
+            self.line.push_str("}}\n");
+            self.out.write_all(self.line.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let timestamp = p
+            .last_update()
+            .duration_since(time::SystemTime::UNIX_EPOCH)?
+            .as_secs_f64();
+
+        self.line.clear();
+        write!(
+            self.line,
+            "{{\"last_update\":{:.5},\"annotations\":[",
+            timestamp
+        )?;
+
+        for (i, annotation) in p.annotations().iter().enumerate() {
+            if i != 0 {
+                self.line.push(',');
+            }
+            write_string(&mut self.line, annotation);
+        }
+
+        self.line.push_str("],\"channels\":[");
+
+        for (i, ch) in p.channels().iter().enumerate() {
+            if i != 0 {
+                self.line.push(',');
+            }
+
+            self.line.push_str("{\"name\":");
+            write_string(&mut self.line, ch.name());
+            write!(
+                self.line,
+                ",\"id\":{},\"voltage\":{},\"current\":{},\"energy\":{},\"power\":{}}}",
+                ch.id(),
+                ch.voltage(),
+                ch.current(),
+                ch.energy(),
+                ch.power()
+            )?;
+        }
+
+        self.line.push_str("]}\n");
+        self.out.write_all(self.line.as_bytes())?;
+
+        Ok(ControlFlow::Continue)
+    }
+
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+/// Subscriber writing samples to `path` as newline-delimited JSON, one object per line as
+/// described by `data_json_schema`. With `metadata`, the first line is an object with the single
+/// key `metadata` holding the fields of the run's `RunMetadata` as strings. Refuses to overwrite a
+/// non-empty file. Virtual channels are not written.
+pub fn create_json(
+    path: &Path,
+    metadata: bool,
+) -> Result<Box<dyn PoweneticsSubscriber>, JsonError> {
+    if path.try_exists()? && fs::metadata(path)?.len() != 0 {
+        return Err(JsonError::JsonExists);
+    }
+
+    let sub = JsonSubscriber {
+        out: BufWriter::new(File::create(path)?),
+        line: String::new(),
+        metadata,
+    };
+
+    Ok(Box::new(sub))
+}
+
+/// Write all samples of the measurement to `path` as newline-delimited JSON, see `create_json`
+pub fn subscribe_json(
+    p: &mut Powenetics,
+    path: &Path,
+    metadata: bool,
+) -> Result<SubscriptionId, JsonError> {
+    Ok(p.subscribe(create_json(path, metadata)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+    use crate::POWENETICS_CHANNELS;
+
+    fn write_json(name: &str, metadata: bool) -> Vec<String> {
+        let path = std::env::temp_dir().join(format!("powenetics-{}-{}", std::process::id(), name));
+        let port = MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(3)));
+        subscribe_json(&mut p, &path, metadata).unwrap();
+        p.start_measurement().unwrap();
+
+        let lines = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        lines.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn metadata_precedes_samples() {
+        let lines = write_json("metadata.json", true);
+        let lines: Vec<serde_json::Value> = lines
+            .iter()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        assert_eq!(lines.len(), 4);
+
+        let metadata = lines[0]["metadata"].as_object().unwrap();
+        let keys: Vec<&str> = metadata.keys().map(String::as_str).collect();
+        assert_eq!(
+            keys,
+            [
+                "calibrated_channels",
+                "crate_version",
+                "device_banner",
+                "port_name",
+                "run_id",
+                "start_time"
+            ]
+        );
+        assert_eq!(metadata["port_name"], "mock");
+        assert_eq!(metadata["calibrated_channels"], "none");
+        assert!(metadata.values().all(serde_json::Value::is_string));
+
+        for sample in &lines[1..] {
+            assert!(sample["last_update"].as_f64().unwrap() > 0.0);
+            assert_eq!(sample["annotations"], serde_json::json!([]));
+
+            let channels = sample["channels"].as_array().unwrap();
+            assert_eq!(channels.len(), POWENETICS_CHANNELS.len());
+
+            for (i, (channel, name)) in channels.iter().zip(POWENETICS_CHANNELS).enumerate() {
+                assert_eq!(channel["name"], name);
+                assert_eq!(channel["id"], i);
+                assert_eq!(channel["voltage"], 12_000);
+                assert_eq!(channel["current"], 1_000);
+                assert_eq!(channel["power"], 12_000_000);
+                assert!(channel["energy"].is_u64());
+            }
+        }
+    }
+
+    #[test]
+    fn metadata_is_optional() {
+        let lines = write_json("samples.json", false);
+
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("{\"last_update\":"));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::{ParsedPacket, PoweneticsError, POWENETICS_CHANNELS};
+
+/// Byte order of multi-byte fields
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Big,
+    Little,
+}
+
+/// Position of a field within a packet or, for channel fields, within a channel's record
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketField {
+    pub offset: usize,
+    pub size: usize,
+}
+
+/// Layout of a measurement packet.
+///
+/// A packet starts with a fixed header followed by a sequence number and one record per channel
+/// in protocol order. Sequence and voltage fields may be at most 2 bytes and current fields at
+/// most 4 bytes wide, and all fields must lie within `size`; see `validate`. Packets are decoded
+/// by a `PacketParser`, which checks the layout once when created.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketLayout {
+    /// Total size of a packet in bytes
+    pub size: usize,
+    /// Bytes every packet starts with
+    pub header: [u8; 2],
+    pub sequence: PacketField,
+    /// Offset of the first channel record
+    pub channels_offset: usize,
+    /// Distance between the starts of two consecutive channel records
+    pub channel_stride: usize,
+    /// Voltage in mV, relative to the start of the channel record
+    pub voltage: PacketField,
+    /// Current in mA, relative to the start of the channel record
+    pub current: PacketField,
+    pub endianness: Endianness,
+}
+
+impl PacketLayout {
+    /// Layout of the 69-byte packets sent by the device: header `CA AC`, a 2-byte sequence number
+    /// and 13 records of a 2-byte voltage followed by a 3-byte current, all big-endian. The
+    /// records fill the packet completely, there is no room for a checksum.
+    pub const DEFAULT: PacketLayout = PacketLayout {
+        size: 69,
+        header: [0xCA, 0xAC],
+        sequence: PacketField { offset: 2, size: 2 },
+        channels_offset: 4,
+        channel_stride: 5,
+        voltage: PacketField { offset: 0, size: 2 },
+        current: PacketField { offset: 2, size: 3 },
+        endianness: Endianness::Big,
+    };
+
+    /// Offset of a channel's voltage within the packet
+    pub fn voltage_offset(&self, channel: usize) -> usize {
+        self.channels_offset + channel * self.channel_stride + self.voltage.offset
+    }
+
+    /// Offset of a channel's current within the packet
+    pub fn current_offset(&self, channel: usize) -> usize {
+        self.channels_offset + channel * self.channel_stride + self.current.offset
+    }
+
+    /// Check that all fields fit into `size` and their values into the decoded types, failing with
+    /// `PoweneticsError::Protocol` otherwise
+    pub fn validate(&self) -> Result<(), PoweneticsError> {
+        let last_record = self
+            .channel_stride
+            .checked_mul(POWENETICS_CHANNELS.len() - 1)
+            .and_then(|offset| offset.checked_add(self.channels_offset));
+
+        let fields = [
+            ("sequence", Some(0), self.sequence, 2),
+            ("voltage", last_record, self.voltage, 2),
+            ("current", last_record, self.current, 4),
+        ];
+
+        if self.size < self.header.len() {
+            return Err(PoweneticsError::Protocol {
+                message: format!("header exceeds the packet size of {}", self.size),
+            });
+        }
+
+        for (name, base, field, max_size) in fields {
+            if field.size == 0 || field.size > max_size {
+                return Err(PoweneticsError::Protocol {
+                    message: format!(
+                        "{} field of {} bytes, expected 1 to {}",
+                        name, field.size, max_size
+                    ),
+                });
+            }
+
+            let end = base
+                .and_then(|base| base.checked_add(field.offset))
+                .and_then(|offset| offset.checked_add(field.size));
+
+            if end.is_none_or(|end| end > self.size) {
+                return Err(PoweneticsError::Protocol {
+                    message: format!("{} field exceeds the packet size of {}", name, self.size),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encode a packet laid out this way, the inverse of `PacketParser::parse`. Readings wider
+    /// than their fields are truncated.
+    pub(crate) fn encode(&self, packet: &ParsedPacket) -> Vec<u8> {
+        let mut buf = vec![0; self.size];
+
+        buf[..2].copy_from_slice(&self.header);
+        self.write(
+            &mut buf,
+            self.sequence.offset,
+            self.sequence.size,
+            packet.sequence as u32,
+        );
+
+        for i in 0..POWENETICS_CHANNELS.len() {
+            self.write(
+                &mut buf,
+                self.voltage_offset(i),
+                self.voltage.size,
+                packet.voltages[i] as u32,
+            );
+            self.write(
+                &mut buf,
+                self.current_offset(i),
+                self.current.size,
+                packet.currents[i],
+            );
+        }
+
+        buf
+    }
+
+    fn write(&self, buf: &mut [u8], offset: usize, size: usize, value: u32) {
+        let bytes = &mut buf[offset..offset + size];
+
+        match self.endianness {
+            Endianness::Big => bytes.copy_from_slice(&value.to_be_bytes()[4 - size..]),
+            Endianness::Little => bytes.copy_from_slice(&value.to_le_bytes()[..size]),
+        }
+    }
+
+    fn read(&self, buf: &[u8], offset: usize, size: usize) -> u32 {
+        let bytes = &buf[offset..offset + size];
+
+        match self.endianness {
+            Endianness::Big => bytes.iter().fold(0, |value, &b| value << 8 | b as u32),
+            Endianness::Little => bytes
+                .iter()
+                .rev()
+                .fold(0, |value, &b| value << 8 | b as u32),
+        }
+    }
+}
+
+impl Default for PacketLayout {
+    fn default() -> Self {
+        PacketLayout::DEFAULT
+    }
+}
+
+/// Decodes packets of a `PacketLayout` that passed `PacketLayout::validate`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PacketParser(PacketLayout);
+
+impl PacketParser {
+    /// Parser for `PacketLayout::DEFAULT`
+    pub const DEFAULT: PacketParser = PacketParser(PacketLayout::DEFAULT);
+
+    /// Check `layout` with `PacketLayout::validate`
+    pub fn new(layout: PacketLayout) -> Result<Self, PoweneticsError> {
+        layout.validate()?;
+        Ok(PacketParser(layout))
+    }
+
+    pub fn layout(&self) -> &PacketLayout {
+        &self.0
+    }
+
+    /// Decode a packet. Only the header is checked.
+    pub fn parse(&self, buf: &[u8]) -> Result<ParsedPacket, PoweneticsError> {
+        let layout = &self.0;
+
+        if buf.len() < layout.size {
+            return Err(PoweneticsError::Protocol {
+                message: format!(
+                    "expected a packet of {} bytes, received {}",
+                    layout.size,
+                    buf.len()
+                ),
+            });
+        }
+
+        if buf[..2] != layout.header {
+            return Err(PoweneticsError::Protocol {
+                message: format!(
+                    "expected [{:#04X}, {:#04X}], received [{:#04X}, {:#04X}]",
+                    layout.header[0], layout.header[1], buf[0], buf[1]
+                ),
+            });
+        }
+
+        let mut packet = ParsedPacket {
+            sequence: layout.read(buf, layout.sequence.offset, layout.sequence.size) as u16,
+            voltages: [0; POWENETICS_CHANNELS.len()],
+            currents: [0; POWENETICS_CHANNELS.len()],
+        };
+
+        for i in 0..POWENETICS_CHANNELS.len() {
+            packet.voltages[i] =
+                layout.read(buf, layout.voltage_offset(i), layout.voltage.size) as u16;
+            packet.currents[i] = layout.read(buf, layout.current_offset(i), layout.current.size);
+        }
+
+        Ok(packet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_layout_is_valid() {
+        assert_eq!(
+            PacketParser::new(PacketLayout::DEFAULT).unwrap(),
+            PacketParser::DEFAULT
+        );
+    }
+
+    #[test]
+    fn oversized_fields_are_rejected() {
+        let layout = PacketLayout {
+            current: PacketField { offset: 0, size: 5 },
+            ..PacketLayout::DEFAULT
+        };
+
+        assert!(matches!(
+            PacketParser::new(layout),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+    }
+
+    #[test]
+    fn fields_outside_the_packet_are_rejected() {
+        let layout = PacketLayout {
+            channel_stride: 6,
+            ..PacketLayout::DEFAULT
+        };
+
+        assert!(matches!(
+            PacketParser::new(layout),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+
+        let layout = PacketLayout {
+            channels_offset: usize::MAX,
+            ..PacketLayout::DEFAULT
+        };
+
+        assert!(layout.validate().is_err());
+    }
+
+    #[test]
+    fn little_endian_fields_are_decoded() {
+        let layout = PacketLayout {
+            endianness: Endianness::Little,
+            ..PacketLayout::DEFAULT
+        };
+        let mut buf = [0; 69];
+        buf[..2].copy_from_slice(&layout.header);
+        buf[2..4].copy_from_slice(&[0x34, 0x12]);
+        buf[4..9].copy_from_slice(&[0xE8, 0x2E, 0x40, 0x42, 0x0F]);
+
+        let packet = PacketParser::new(layout).unwrap().parse(&buf).unwrap();
+        assert_eq!(packet.sequence, 0x1234);
+        assert_eq!(packet.voltages[0], 12_008);
+        assert_eq!(packet.currents[0], 1_000_000);
+    }
+
+    #[test]
+    fn encoded_packets_parse_back() {
+        let packet = ParsedPacket {
+            sequence: 0xBEEF,
+            voltages: std::array::from_fn(|i| 12_000 + i as u16),
+            currents: std::array::from_fn(|i| 0xFF_0000 + i as u32),
+        };
+
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let layout = PacketLayout {
+                endianness,
+                ..PacketLayout::DEFAULT
+            };
+
+            let parser = PacketParser::new(layout).unwrap();
+            assert_eq!(parser.parse(&layout.encode(&packet)).unwrap(), packet);
+        }
+    }
+}
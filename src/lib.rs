@@ -1,18 +1,86 @@
 use core::array;
 use std::array::TryFromSliceError;
+use std::cmp;
+use std::collections::VecDeque;
 use std::fmt::Debug;
 use std::io::Read;
-use std::{io, thread, time};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::{fs, io, thread, time};
 
-use serialport::SerialPort;
+use serialport::{ClearBuffer, SerialPort};
 use thiserror::Error;
 
+use crate::health::{DropWindow, IoStats, LinkCounters, LinkHealth};
+use crate::layout::{PacketLayout, PacketParser};
+use crate::metadata::RunMetadata;
+use crate::virtual_channel::{VirtualChannel, VirtualChannelFn};
+
+pub mod alert;
+pub mod background;
+pub mod bucket;
+pub mod calibration;
+pub mod csv_replay;
+pub mod csv_row;
+pub mod dropout;
+mod emulated;
+pub mod filter;
+pub mod health;
+pub mod influx;
+pub mod json;
+pub mod layout;
+pub mod limit;
+pub mod metadata;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod mock;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod replay;
+pub mod report;
+pub mod ring;
+pub mod schema;
+#[cfg(feature = "serde")]
+mod serialization;
+pub mod stats;
+#[cfg(feature = "async")]
+pub mod stream;
+pub mod trigger;
+pub mod validate;
+pub mod virtual_channel;
+pub mod window;
+pub mod writer;
+
 const POWENETICS_BAUD_RATE: u32 = 921600;
+const POWENETICS_TIMEOUT: time::Duration = time::Duration::from_millis(5);
 const POWENETICS_DATA_BITS: serialport::DataBits = serialport::DataBits::Eight;
 const POWENETICS_SERIAL_PARITY: serialport::Parity = serialport::Parity::None;
 const POWENETICS_STOP_BITS: serialport::StopBits = serialport::StopBits::One;
-const POWENETICS_MEASUREMENT_PACKET_SIZE: usize = 69;
+pub const POWENETICS_MEASUREMENT_PACKET_SIZE: usize = PacketLayout::DEFAULT.size;
 const POWENETICS_READY_MESSAGE: &str = "PMD is ready!";
+const POWENETICS_START_COMMAND: [u8; 4] = [0xCA, 0xAC, 0xBD, 0x90];
+const POWENETICS_COMMAND_TIMEOUT: time::Duration = time::Duration::from_millis(1);
+const POWENETICS_COMMAND_POLL_INTERVAL: time::Duration = time::Duration::from_micros(100);
+/// Time to wait for the first data after sending the start command before sending it again
+const POWENETICS_START_TIMEOUT: time::Duration = time::Duration::from_millis(100);
+const POWENETICS_DEFAULT_START_RETRIES: u32 = 3;
+/// Time to wait for the device to announce itself after resetting the calibration
+const POWENETICS_RESET_TIMEOUT: time::Duration = time::Duration::from_millis(500);
+/// Delay before the first reconnect attempt, doubled for every further attempt
+const POWENETICS_RECONNECT_BACKOFF: time::Duration = time::Duration::from_millis(100);
+const POWENETICS_RECONNECT_MAX_BACKOFF: time::Duration = time::Duration::from_secs(5);
+/// Bytes to scan for the next packet header during error recovery before giving up
+const POWENETICS_RESYNC_LIMIT: usize = 16 * POWENETICS_MEASUREMENT_PACKET_SIZE;
+
+/// Annotation attached to the first sample after the device reset itself mid-stream
+pub const DEVICE_RESET_ANNOTATION: &str = "device reset";
+/// Annotation attached to the first sample after the port was reopened mid-stream
+pub const RECONNECT_ANNOTATION: &str = "reconnected";
+
+/// Resolution of voltage readings in mV
+pub const POWENETICS_VOLTAGE_LSB: u16 = 1;
+/// Resolution of current readings in mA
+pub const POWENETICS_CURRENT_LSB: u32 = 1;
 
 pub const POWENETICS_USB_VID: u16 = 0x4d8;
 pub const POWENETICS_USB_PID: u16 = 0xa;
@@ -33,10 +101,248 @@ pub const POWENETICS_CHANNELS: [&str; 13] = [
     "PCIe 12V #1",
 ];
 
-pub trait PoweneticsSubscriber {
+/// Type-safe reference to one of the channels, in protocol order
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ChannelId {
+    Atx3V3,
+    Atx5VStandby,
+    Atx12V,
+    Atx5V,
+    Eps12V1,
+    Atx12vo12VStandby,
+    Eps12V3,
+    Eps12V2,
+    Pcie12V3,
+    Pcie12V2,
+    PcieSlot3V3,
+    PcieSlot12V,
+    Pcie12V1,
+}
+
+impl ChannelId {
+    pub const ALL: [ChannelId; POWENETICS_CHANNELS.len()] = [
+        ChannelId::Atx3V3,
+        ChannelId::Atx5VStandby,
+        ChannelId::Atx12V,
+        ChannelId::Atx5V,
+        ChannelId::Eps12V1,
+        ChannelId::Atx12vo12VStandby,
+        ChannelId::Eps12V3,
+        ChannelId::Eps12V2,
+        ChannelId::Pcie12V3,
+        ChannelId::Pcie12V2,
+        ChannelId::PcieSlot3V3,
+        ChannelId::PcieSlot12V,
+        ChannelId::Pcie12V1,
+    ];
+
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+
+    pub fn from_u8(id: u8) -> Option<ChannelId> {
+        ChannelId::ALL.get(id as usize).copied()
+    }
+
+    pub fn from_name(name: &str) -> Option<ChannelId> {
+        POWENETICS_CHANNELS
+            .iter()
+            .position(|n| *n == name)
+            .map(|i| ChannelId::ALL[i])
+    }
+
+    /// Default name as listed in `POWENETICS_CHANNELS`
+    pub fn name(self) -> &'static str {
+        POWENETICS_CHANNELS[self as usize]
+    }
+
+    /// Nominal rail voltage in mV
+    pub fn nominal_voltage(self) -> u16 {
+        match self {
+            ChannelId::Atx3V3 | ChannelId::PcieSlot3V3 => 3300,
+            ChannelId::Atx5VStandby | ChannelId::Atx5V => 5000,
+            _ => 12000,
+        }
+    }
+
+    pub fn group(self) -> ChannelGroup {
+        match self {
+            ChannelId::Atx3V3
+            | ChannelId::Atx5VStandby
+            | ChannelId::Atx12V
+            | ChannelId::Atx5V
+            | ChannelId::Atx12vo12VStandby => ChannelGroup::Atx,
+            ChannelId::Eps12V1 | ChannelId::Eps12V2 | ChannelId::Eps12V3 => ChannelGroup::Eps,
+            ChannelId::Pcie12V1 | ChannelId::Pcie12V2 | ChannelId::Pcie12V3 => ChannelGroup::Pcie,
+            ChannelId::PcieSlot3V3 | ChannelId::PcieSlot12V => ChannelGroup::PcieSlot,
+        }
+    }
+
+    /// Position in `ChannelOrder::Group`
+    fn display_rank(self) -> usize {
+        CHANNEL_DISPLAY_ORDER
+            .iter()
+            .position(|id| *id == self)
+            .unwrap_or(CHANNEL_DISPLAY_ORDER.len())
+    }
+}
+
+/// Channels grouped by connector, each group and its channels in ascending voltage and number
+const CHANNEL_DISPLAY_ORDER: [ChannelId; POWENETICS_CHANNELS.len()] = [
+    ChannelId::Atx3V3,
+    ChannelId::Atx5V,
+    ChannelId::Atx5VStandby,
+    ChannelId::Atx12V,
+    ChannelId::Atx12vo12VStandby,
+    ChannelId::Eps12V1,
+    ChannelId::Eps12V2,
+    ChannelId::Eps12V3,
+    ChannelId::Pcie12V1,
+    ChannelId::Pcie12V2,
+    ChannelId::Pcie12V3,
+    ChannelId::PcieSlot3V3,
+    ChannelId::PcieSlot12V,
+];
+
+/// Connector a channel is measured on
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChannelGroup {
+    /// ATX 24-pin connector
+    Atx,
+    /// EPS CPU power connectors
+    Eps,
+    /// PCIe auxiliary power connectors
+    Pcie,
+    /// PCIe slot (via riser)
+    PcieSlot,
+}
+
+/// Orderings for presenting channels, see `PoweneticsData::channels_ordered`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ChannelOrder {
+    /// Order in which the device reports the channels
+    Protocol,
+    /// Ascending nominal voltage, channels of equal voltage in protocol order
+    NominalVoltage,
+    /// By connector, see `ChannelGroup`
+    Group,
+    /// Descending current power, channels of equal power in protocol order
+    Power,
+}
+
+/// Action requested by a subscriber after processing a sample
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ControlFlow {
+    Continue,
+    /// Stop the measurement after all subscribers have processed the current sample
+    Stop,
+    /// Reset the energy of all channels after all subscribers have processed the current sample
+    ResetEnergy,
+    /// Attach a marker to the next sample, see `PoweneticsData::annotations`
+    Annotate(String),
+    /// Remove this subscriber after all subscribers have processed the current sample. It is not
+    /// notified of the end of the measurement. Removing the last subscriber stops the measurement.
+    Unsubscribe,
+}
+
+/// Compatibility with subscribers returning whether to stop the measurement
+impl From<bool> for ControlFlow {
+    fn from(stop: bool) -> Self {
+        if stop {
+            ControlFlow::Stop
+        } else {
+            ControlFlow::Continue
+        }
+    }
+}
+
+/// Subscriber interface of earlier versions, returning whether to stop the measurement. Wrap
+/// implementations in `BoolSubscriber` to subscribe them unchanged.
+pub trait StopSubscriber {
     fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<bool>;
 }
 
+/// Adapter subscribing a `StopSubscriber`, with `true` mapping to `ControlFlow::Stop` and `false`
+/// to `ControlFlow::Continue`
+pub struct BoolSubscriber<S: StopSubscriber + Send>(pub S);
+
+impl<S: StopSubscriber + Send> PoweneticsSubscriber for BoolSubscriber<S> {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        self.0.update(p).map(ControlFlow::from)
+    }
+}
+
+/// Receives the samples of a measurement. Subscribers must be `Send` so that the measurement can
+/// run on a background thread, see `Powenetics::start_measurement_threaded`.
+///
+/// Earlier versions returned `anyhow::Result<bool>` from `update` and did not require `Send`.
+/// Subscribers written against that interface can implement `StopSubscriber` instead and be
+/// wrapped in `BoolSubscriber`; non-`Send` state such as `Rc` has to be replaced with its
+/// thread-safe counterparts (e.g. `Arc`).
+pub trait PoweneticsSubscriber: Send {
+    /// Called once when the measurement starts, before the first update
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow>;
+
+    /// Called once when the measurement ends cleanly, after the final update. Not called if the
+    /// measurement ends due to an error, e.g. when the device is disconnected, so its absence lets
+    /// consumers of streamed data tell an abrupt end from a clean stop.
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Why a measurement ended cleanly
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// A subscriber returned `ControlFlow::Stop`
+    Subscriber,
+    /// Requested through a `StopHandle`
+    Requested,
+    /// The last subscriber returned `ControlFlow::Unsubscribe`
+    NoSubscribers,
+    /// The drop rate exceeded the limit set with `Powenetics::set_max_drop_rate`
+    ExcessiveDrops,
+    /// All samples of a recording have been replayed, see `csv_replay::replay_csv`
+    EndOfInput,
+}
+
+/// Allows keeping access to a subscriber (e.g. to query results) while it is subscribed
+impl<T: PoweneticsSubscriber> PoweneticsSubscriber for Arc<Mutex<T>> {
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.lock()
+            .map_err(|_| anyhow::anyhow!("subscriber mutex poisoned"))?
+            .begin(metadata, p)
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        self.lock()
+            .map_err(|_| anyhow::anyhow!("subscriber mutex poisoned"))?
+            .update(p)
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.lock()
+            .map_err(|_| anyhow::anyhow!("subscriber mutex poisoned"))?
+            .end(reason, p)
+    }
+}
+
+/// Adapter for closures passed to `Powenetics::with_subscriber_fn`
+struct FnSubscriber<F>(F);
+
+impl<F> PoweneticsSubscriber for FnSubscriber<F>
+where
+    F: FnMut(&PoweneticsData) -> anyhow::Result<ControlFlow> + Send,
+{
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        (self.0)(p)
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum PoweneticsError {
     #[error("Serial port error")]
@@ -53,6 +359,10 @@ pub enum PoweneticsError {
     },
     #[error(transparent)]
     Subscriber(anyhow::Error),
+    #[error("Serial port error while awaiting calibration response")]
+    CalibrationResponse(#[source] serialport::Error),
+    #[error("Serial port error while awaiting command response")]
+    CommandResponse(#[source] serialport::Error),
 
     #[error("Unable to change measurement configuration after measurement has already started")]
     MeasurementAlreadyStarted,
@@ -60,18 +370,37 @@ pub enum PoweneticsError {
     InvalidChannel,
     #[error("No power on channel, cannot calibrate")]
     NoPowerOnChannel,
+    #[error("No power on channels {}, cannot calibrate", channel_names(.channels))]
+    NoPowerOnChannels { channels: Vec<ChannelId> },
     #[error("No listeners specified")]
     NoSubscribers,
+    #[error("Serial port is already in use by another process")]
+    PortBusy(#[source] serialport::Error),
+    #[error("Not a Powenetics device (USB VID {vid:#06x}, PID {pid:#06x})")]
+    NotPowenetics { vid: u16, pid: u16 },
+    #[error("No responsive Powenetics device found")]
+    NoDeviceFound,
+    #[error("Device did not start streaming after {attempts} start commands")]
+    StartTimeout { attempts: u32 },
     #[error("Powenetics protocol error, unplug and reconnect device. Reason: {message}")]
     Protocol { message: String },
+    #[error("Expected {expected} channel names, got {count}", expected = POWENETICS_CHANNELS.len())]
+    ChannelNameCount { count: usize },
 }
 
+#[derive(Clone)]
 pub struct Channel {
     name: String,
     id: u8,
     voltage: u16,
     current: u32,
+    // Cached product of voltage and current, computed once per sample
+    power: u32,
+    // Power of the previous sample, for trapezoidal integration
+    previous_power: u32,
     energy: u64,
+    energy_secondary: u64,
+    quantization_error: u64,
     last_update: time::SystemTime,
 }
 
@@ -84,6 +413,10 @@ impl Channel {
         self.id
     }
 
+    pub fn channel_id(&self) -> ChannelId {
+        ChannelId::ALL[self.id as usize]
+    }
+
     pub fn voltage(&self) -> u16 {
         self.voltage
     }
@@ -93,292 +426,3254 @@ impl Channel {
     }
 
     pub fn power(&self) -> u32 {
-        self.voltage as u32 * self.current
+        self.power
+    }
+
+    pub(crate) fn set_reading(&mut self, voltage: u16, current: u32) {
+        self.voltage = voltage;
+        self.current = current;
+        self.previous_power = self.power;
+        self.power = voltage as u32 * current;
     }
 
     pub fn energy(&self) -> u64 {
         self.energy
     }
 
-    fn update_energy(&mut self, time: time::SystemTime) -> Result<(), PoweneticsError> {
+    pub fn voltage_volts(&self) -> f64 {
+        (self.voltage * POWENETICS_VOLTAGE_LSB) as f64 / 1_000.0
+    }
+
+    pub fn current_amps(&self) -> f64 {
+        (self.current * POWENETICS_CURRENT_LSB) as f64 / 1_000.0
+    }
+
+    pub fn power_watts(&self) -> f64 {
+        self.power as f64 / 1_000_000.0
+    }
+
+    pub fn energy_joules(&self) -> f64 {
+        self.energy as f64 / 1_000_000_000.0
+    }
+
+    pub fn energy_wh(&self) -> f64 {
+        self.energy_joules() / 3_600.0
+    }
+
+    /// Energy in nJ accumulated alongside `energy` but reset independently with
+    /// `reset_energy_secondary`, e.g. for per-iteration energy within a run
+    pub fn energy_secondary(&self) -> u64 {
+        self.energy_secondary
+    }
+
+    /// Move the reference time to `time`, accumulating the energy of the interval only if
+    /// `integrate` is set. Returns the energy of the interval either way, which is zero if the
+    /// system clock stepped backwards.
+    fn update_energy(
+        &mut self,
+        time: time::SystemTime,
+        integrate: bool,
+        method: IntegrationMethod,
+    ) -> u64 {
+        let mut energy = 0;
+
         if self.last_update != time::SystemTime::UNIX_EPOCH {
-            let duration = time.duration_since(self.last_update)?;
+            let duration = time.duration_since(self.last_update).unwrap_or_default();
+            let power = method.interval_power(self.previous_power as u64, self.power as u64);
+
+            energy = power * (duration.as_micros() as u64) / 1000;
 
-            self.energy += (self.power() as u64) * (duration.as_micros() as u64) / 1000;
+            if integrate {
+                self.energy += energy;
+                self.energy_secondary += energy;
+
+                // Maximum power error due to rounding voltage and current to half an LSB each
+                let power_error = (self.voltage as u64 * POWENETICS_CURRENT_LSB as u64
+                    + self.current as u64 * POWENETICS_VOLTAGE_LSB as u64)
+                    / 2;
+                self.quantization_error += power_error * (duration.as_micros() as u64) / 1000;
+            }
         }
 
         self.last_update = time;
-        Ok(())
+        energy
     }
 
     pub fn reset_energy(&mut self) {
         self.energy = 0;
+        self.quantization_error = 0;
+    }
+
+    pub fn reset_energy_secondary(&mut self) {
+        self.energy_secondary = 0;
     }
 }
 
 pub struct Powenetics {
-    subscriptions: Vec<Box<dyn PoweneticsSubscriber>>,
+    // Sorted by descending priority, in order of registration within the same priority
+    subscriptions: Vec<Subscription>,
+    next_subscription_id: u64,
+    samples_processed: u64,
     data: PoweneticsData,
     port: Box<dyn SerialPort>,
+    port_name: String,
+    // `None` for ports passed to `from_port`, which cannot be reopened
+    port_settings: Option<PortSettings>,
     started: bool,
+    preserve_energy: bool,
+    start_retries: u32,
+    calibration_echo: bool,
+    banner: Option<String>,
+    calibration: [Option<u32>; POWENETICS_CHANNELS.len()],
+    metadata: Option<RunMetadata>,
+    link: LinkCounters,
+    io: IoStats,
+    // Whether the stream has been started for `read_into`, and the sequence expected next
+    pulling: bool,
+    pull_sequence: Option<u16>,
+    virtual_channel_fns: Vec<VirtualChannelFn>,
+    off_threshold: Option<u64>,
+    integration: IntegrationMethod,
+    monotonic_clock: bool,
+    // Monotonic and wall-clock time of the first sample of the measurement
+    measurement_start: Option<(time::Instant, time::SystemTime)>,
+    history: VecDeque<OwnedSample>,
+    history_capacity: usize,
+    max_drop_rate: Option<f64>,
+    drop_window: DropWindow,
+    error_recovery: bool,
+    stop_requested: Arc<AtomicBool>,
+    stop_reason: Option<StopReason>,
+}
+
+/// How energy is integrated from the power of consecutive samples, see
+/// `Powenetics::set_integration_method`
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum IntegrationMethod {
+    /// Power of the current sample times the interval since the previous sample
+    #[default]
+    Rectangular,
+    /// Mean power of the previous and the current sample times the interval between them, more
+    /// accurate where the load changes between samples
+    Trapezoidal,
+}
+
+impl IntegrationMethod {
+    /// Power in µW to integrate over the interval between two samples
+    pub(crate) fn interval_power(self, previous: u64, current: u64) -> u64 {
+        match self {
+            IntegrationMethod::Rectangular => current,
+            IntegrationMethod::Trapezoidal => (previous + current) / 2,
+        }
+    }
+}
+
+/// Identifies a subscription for `Powenetics::unsubscribe`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u64);
+
+struct Subscription {
+    id: SubscriptionId,
+    priority: i32,
+    subscriber: Box<dyn PoweneticsSubscriber>,
+}
+
+/// Allows stopping a running measurement from another thread, see `Powenetics::stop_handle`
+#[derive(Clone, Debug)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// Request the measurement to stop after all subscribers have processed the current sample. If
+    /// no measurement is running, the next one stops after its first sample.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Device sample period as estimated by `Powenetics::estimate_sample_period`
+#[derive(Copy, Clone, Debug)]
+pub struct SamplePeriod {
+    pub mean: time::Duration,
+    /// Variance of the inter-packet interval in s²
+    pub variance: f64,
 }
 
+#[derive(Clone)]
 pub struct PoweneticsData {
     channels: [Channel; POWENETICS_CHANNELS.len()],
     last_update: time::SystemTime,
+    annotations: Vec<String>,
+    excluded_from_totals: [bool; POWENETICS_CHANNELS.len()],
+    virtual_channels: Vec<VirtualChannel>,
+    off_energy: u64,
+    sample_index: u64,
+    elapsed: time::Duration,
+    sequence: u16,
+    dropped_samples: u64,
 }
 
-pub fn new(path: &str) -> Result<Powenetics, PoweneticsError> {
-    let port = serialport::new(path, POWENETICS_BAUD_RATE)
-        .parity(POWENETICS_SERIAL_PARITY)
-        .data_bits(POWENETICS_DATA_BITS)
-        .stop_bits(POWENETICS_STOP_BITS)
-        .timeout(time::Duration::from_millis(5))
-        .open()?;
-
-    let channels = array::from_fn(|i| Channel {
-        name: String::from(POWENETICS_CHANNELS[i]),
-        id: i as u8,
-        voltage: 0,
-        current: 0,
-        energy: 0,
-        last_update: time::SystemTime::UNIX_EPOCH,
-    });
-
-    let powenetics = Powenetics {
-        port,
-        started: false,
-        data: PoweneticsData {
-            channels,
-            last_update: time::SystemTime::UNIX_EPOCH,
-        },
-        subscriptions: vec![],
-    };
-    Ok(powenetics)
+/// Copy of the per-channel values of a `PoweneticsData` that does not borrow the device, e.g. to
+/// keep samples beyond `update` or send them to another thread. Channel names and annotations
+/// are not included.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OwnedSample {
+    last_update: time::SystemTime,
+    elapsed: time::Duration,
+    sample_index: u64,
+    voltages: [u16; POWENETICS_CHANNELS.len()],
+    currents: [u32; POWENETICS_CHANNELS.len()],
+    energies: [u64; POWENETICS_CHANNELS.len()],
 }
 
-impl PoweneticsData {
-    pub fn channel_by_id(&self, id: usize) -> Result<&Channel, PoweneticsError> {
-        if id > self.channels.len() {
-            return Err(PoweneticsError::InvalidChannel);
+impl Default for OwnedSample {
+    fn default() -> Self {
+        OwnedSample {
+            last_update: time::SystemTime::UNIX_EPOCH,
+            elapsed: time::Duration::ZERO,
+            sample_index: 0,
+            voltages: [0; POWENETICS_CHANNELS.len()],
+            currents: [0; POWENETICS_CHANNELS.len()],
+            energies: [0; POWENETICS_CHANNELS.len()],
         }
-
-        Ok(&self.channels[id])
     }
+}
 
-    pub fn channel_by_name(&self, name: &str) -> Result<&Channel, PoweneticsError> {
-        for (i, n) in POWENETICS_CHANNELS.iter().enumerate() {
-            if *n == name {
-                return Ok(&self.channels[i]);
-            }
-        }
+impl OwnedSample {
+    pub fn last_update(&self) -> time::SystemTime {
+        self.last_update
+    }
 
-        Err(PoweneticsError::InvalidChannel)
+    /// See `PoweneticsData::elapsed`
+    pub fn elapsed(&self) -> time::Duration {
+        self.elapsed
     }
 
-    pub fn channels(&self) -> &[Channel; POWENETICS_CHANNELS.len()] {
-        &self.channels
+    pub fn voltages(&self) -> &[u16; POWENETICS_CHANNELS.len()] {
+        &self.voltages
     }
 
-    pub fn last_update(&self) -> time::SystemTime {
-        self.last_update
+    pub fn currents(&self) -> &[u32; POWENETICS_CHANNELS.len()] {
+        &self.currents
     }
-}
 
-impl Powenetics {
-    pub fn calibrate(&mut self, channel: &Channel, reference: u32) -> Result<(), PoweneticsError> {
-        if self.started {
-            return Err(PoweneticsError::MeasurementAlreadyStarted);
-        }
+    pub fn energies(&self) -> &[u64; POWENETICS_CHANNELS.len()] {
+        &self.energies
+    }
 
-        self.port.write_all(&[0xCA])?;
-        self.port.write_all(&[channel.id])?;
-        self.port.write_all(&reference.to_be_bytes()[1..])?;
-        self.port.flush()?;
+    /// See `PoweneticsData::sample_index`
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
 
-        thread::sleep(time::Duration::from_millis(1));
+    /// Power of all channels in µW, in protocol order
+    pub fn powers(&self) -> [u32; POWENETICS_CHANNELS.len()] {
+        array::from_fn(|i| self.voltages[i] as u32 * self.currents[i])
+    }
 
-        let bytes_to_read = self.port.bytes_to_read()?;
-        if bytes_to_read != 0 {
-            if bytes_to_read == 2 {
-                let mut buf = [0; 2];
+    /// Sum of the power of all channels in µW
+    pub fn total_power(&self) -> u64 {
+        self.powers().iter().map(|&power| power as u64).sum()
+    }
+}
 
-                self.port.read_exact(&mut buf)?;
+/// Serial port settings used when opening a device
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PoweneticsConfig {
+    /// Read timeout of the port (5 ms by default). Raise it where spurious timeouts occur, e.g.
+    /// behind a USB hub.
+    pub timeout: time::Duration,
+    /// Baud rate of the port (921600 by default, which the device expects)
+    pub baud_rate: u32,
+    /// Number of times to reopen the port when reading fails during a measurement, e.g. when
+    /// the device re-enumerated, waiting 100 ms before the first attempt and twice as long
+    /// before every further one (up to 5 s). Read timeouts are not considered a lost connection.
+    /// Disabled (0) by default.
+    pub reconnect_attempts: u32,
+}
 
-                if buf == [0xCA, 0xAC] {
-                    return Err(PoweneticsError::NoPowerOnChannel);
-                } else {
-                    return Err(PoweneticsError::Protocol {
-                        message: format!(
-                            "expected [0xCA, 0xAC], received [{:#04X}, {:#04X}]",
-                            buf[0], buf[1]
-                        ),
-                    });
-                }
-            } else {
-                return Err(PoweneticsError::Protocol {
-                    message: format!("expected 2 bytes, received {}", bytes_to_read),
-                });
-            }
+impl Default for PoweneticsConfig {
+    fn default() -> Self {
+        PoweneticsConfig {
+            timeout: POWENETICS_TIMEOUT,
+            baud_rate: POWENETICS_BAUD_RATE,
+            reconnect_attempts: 0,
         }
-
-        Ok(())
     }
+}
 
-    pub fn reset_calibration(&mut self) -> Result<(), PoweneticsError> {
-        if self.started {
-            return Err(PoweneticsError::MeasurementAlreadyStarted);
-        }
+pub struct PoweneticsBuilder {
+    config: PoweneticsConfig,
+    channel_names: Option<Vec<String>>,
+    exclusive: bool,
+    start_retries: u32,
+    input_buffer_size: Option<u32>,
+    verify_device: bool,
+}
 
-        self.port.write_all(&[0xCA, 0xAC, 0xBD, 0x00])?;
-        self.port.flush()?;
+impl Default for PoweneticsBuilder {
+    fn default() -> Self {
+        PoweneticsBuilder {
+            config: PoweneticsConfig::default(),
+            channel_names: None,
+            exclusive: true,
+            start_retries: POWENETICS_DEFAULT_START_RETRIES,
+            input_buffer_size: None,
+            verify_device: false,
+        }
+    }
+}
 
-        Ok(())
+impl PoweneticsBuilder {
+    pub fn new() -> Self {
+        Self::default()
     }
 
-    fn finalize_calibration(&mut self) -> Result<(), PoweneticsError> {
-        if self.started {
-            return Err(PoweneticsError::MeasurementAlreadyStarted);
-        }
+    /// Serial port settings, see `PoweneticsConfig`
+    pub fn config(mut self, config: PoweneticsConfig) -> Self {
+        self.config = config;
+        self
+    }
 
-        self.port.write_all(&[0xCA, 0xAC, 0xBD, 0x01])?;
-        self.port.flush()?;
+    /// Read timeout of the port, see `PoweneticsConfig::timeout`
+    pub fn timeout(mut self, timeout: time::Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
 
-        thread::sleep(time::Duration::from_millis(1));
+    /// Baud rate of the port, see `PoweneticsConfig::baud_rate`
+    pub fn baud_rate(mut self, baud_rate: u32) -> Self {
+        self.config.baud_rate = baud_rate;
+        self
+    }
 
-        Ok(())
+    /// Names reported by `Channel::name` instead of the defaults, one per channel in protocol
+    /// order. `open` fails with `PoweneticsError::ChannelNameCount` if there are not exactly 13.
+    /// See `Powenetics::set_channel_name` for renaming a single channel.
+    pub fn channel_names<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.channel_names = Some(names.into_iter().map(Into::into).collect());
+        self
     }
 
-    pub fn start_measurement(&mut self) -> Result<(), PoweneticsError> {
-        if self.started {
-            return Err(PoweneticsError::MeasurementAlreadyStarted);
-        }
+    /// Request exclusive access to the port (the default) so that opening it a second time fails
+    /// with `PoweneticsError::PortBusy` instead of two processes competing for the byte stream.
+    /// Serial ports are always opened exclusively on Windows. On Unix, exclusive access is not
+    /// enforced against processes running as root.
+    pub fn exclusive(mut self, exclusive: bool) -> Self {
+        self.exclusive = exclusive;
+        self
+    }
 
-        self.finalize_calibration()?;
+    /// Number of times the start command is re-sent when the device does not start streaming
+    /// within 100 ms (3 by default), e.g. because it is still enumerating
+    pub fn start_retries(mut self, retries: u32) -> Self {
+        self.start_retries = retries;
+        self
+    }
 
-        let bytes_to_read = self.port.bytes_to_read()?;
-        if bytes_to_read != 0 {
-            // Make sure we only read whole packets here
-            let mut buf = vec![
-                0;
-                (bytes_to_read as usize) / POWENETICS_MEASUREMENT_PACKET_SIZE
-                    * POWENETICS_MEASUREMENT_PACKET_SIZE
-            ];
+    /// Request a driver input buffer of `bytes` when opening the port, so that more data can be
+    /// buffered while the host is busy. Only supported on Windows, where the driver may still
+    /// choose a different size. On other platforms, this is a no-op as the kernel's buffer size
+    /// for serial ports is fixed.
+    pub fn input_buffer_size(mut self, bytes: u32) -> Self {
+        self.input_buffer_size = Some(bytes);
+        self
+    }
 
-            self.port.read_exact(&mut buf)?;
+    /// Check that the port belongs to a USB device with the Powenetics VID and PID before opening
+    /// it, failing with `PoweneticsError::NotPowenetics` otherwise. Ports that are not listed as
+    /// USB ports by the operating system are opened with a warning.
+    pub fn verify_device(mut self, verify: bool) -> Self {
+        self.verify_device = verify;
+        self
+    }
 
-            // Upon plugging in, the PMD will send its ready message, however, there is no way to
-            // explicitly stop the measurement other than physically unplugging the device. If the
-            // measurement was aborted and is now restarted without replugging, we will not receive
-            // a ready message but a continuing stream of measurement packets.
-            if !String::from_utf8_lossy(&buf).starts_with(POWENETICS_READY_MESSAGE)
-                && buf[..2] != [0xCA, 0xAC]
-            {
-                return Err(PoweneticsError::Protocol {
-                    message: format!(
-                        "expected \"{}\", received {:?}",
-                        POWENETICS_READY_MESSAGE, buf
-                    ),
-                });
+    pub fn open(self, path: &str) -> Result<Powenetics, PoweneticsError> {
+        if let Some(names) = &self.channel_names {
+            if names.len() != POWENETICS_CHANNELS.len() {
+                return Err(PoweneticsError::ChannelNameCount { count: names.len() });
             }
         }
 
-        // The PMD does not care about further messages even if the measurement is already running
-        self.port.write_all(&[0xCA, 0xAC, 0xBD, 0x90])?;
-        self.port.flush()?;
+        if self.verify_device {
+            verify_device(path, &serialport::available_ports()?)?;
+        }
 
-        self.started = true;
-        self.wait()?;
+        let settings = PortSettings {
+            config: self.config,
+            #[cfg(unix)]
+            exclusive: self.exclusive,
+            #[cfg(windows)]
+            input_buffer_size: self.input_buffer_size,
+        };
 
-        Ok(())
-    }
+        let port = open_port(path, &settings)?;
 
-    fn wait(&mut self) -> Result<(), PoweneticsError> {
-        if self.subscriptions.is_empty() {
-            return Err(PoweneticsError::NoSubscribers);
+        let mut powenetics = from_port(port, path);
+        powenetics.start_retries = self.start_retries;
+        powenetics.port_settings = Some(settings);
+
+        if let Some(names) = self.channel_names {
+            for (channel, name) in powenetics.data.channels.iter_mut().zip(names) {
+                channel.name = name;
+            }
         }
 
-        let mut first_sequence = true;
-        let mut sequence = 1;
+        Ok(powenetics)
+    }
+}
 
-        loop {
-            let mut buf = [0; POWENETICS_MEASUREMENT_PACKET_SIZE];
+/// Settings needed to reopen the port of a `Powenetics` opened by path
+#[derive(Copy, Clone)]
+struct PortSettings {
+    config: PoweneticsConfig,
+    // Ports are always opened exclusively on Windows
+    #[cfg(unix)]
+    exclusive: bool,
+    // Only supported on Windows, see `PoweneticsBuilder::input_buffer_size`
+    #[cfg(windows)]
+    input_buffer_size: Option<u32>,
+}
 
-            self.port.read_exact(&mut buf)?;
-            self.data.last_update = time::SystemTime::now();
+fn open_port(path: &str, settings: &PortSettings) -> Result<Box<dyn SerialPort>, PoweneticsError> {
+    let builder = serialport::new(path, settings.config.baud_rate)
+        .parity(POWENETICS_SERIAL_PARITY)
+        .data_bits(POWENETICS_DATA_BITS)
+        .stop_bits(POWENETICS_STOP_BITS)
+        .timeout(settings.config.timeout);
 
-            if buf[..2] != [0xCA, 0xAC] {
-                return Err(PoweneticsError::Protocol {
-                    message: format!(
-                        "expected [0xCA, 0xAC], received [{:#04X}, {:#04X}]",
-                        buf[0], buf[1]
-                    ),
-                });
-            }
+    #[cfg(unix)]
+    let port: Box<dyn SerialPort> = {
+        let mut port = builder.open_native().map_err(map_open_error)?;
+        port.set_exclusive(settings.exclusive)?;
+        Box::new(port)
+    };
 
-            let sequence_received = u16::from_be_bytes(buf[2..4].try_into().map_err(|err| {
-                PoweneticsError::TryFromSlice {
-                    err,
-                    message: "Failed to parse sequence",
-                }
-            })?);
+    #[cfg(windows)]
+    let port: Box<dyn SerialPort> = {
+        let port = builder.open_native().map_err(map_open_error)?;
 
-            if sequence != sequence_received {
-                if first_sequence {
-                    sequence = sequence_received;
-                    first_sequence = false;
-                } else {
-                    return Err(PoweneticsError::Protocol {
-                        message: format!(
-                            "expected sequence {}, received {}",
-                            sequence, sequence_received
-                        ),
-                    });
-                }
-            }
+        if let Some(bytes) = settings.input_buffer_size {
+            set_input_buffer_size(&port, bytes)?;
+        }
 
-            (sequence, _) = sequence.overflowing_add(1);
+        Box::new(port)
+    };
 
-            for (i, channel) in self.data.channels.iter_mut().enumerate() {
-                let offset = 4 + i * 5;
-                let voltage =
-                    u16::from_be_bytes(buf[offset..offset + 2].try_into().map_err(|err| {
-                        PoweneticsError::TryFromSlice {
-                            err,
-                            message: "Failed to parse voltage",
-                        }
-                    })?);
+    #[cfg(not(any(unix, windows)))]
+    let port = builder.open().map_err(map_open_error)?;
 
-                let current_bytes: [u8; 4] = [0, buf[offset + 2], buf[offset + 3], buf[offset + 4]];
-                let current = u32::from_be_bytes(current_bytes);
+    Ok(port)
+}
 
-                channel.voltage = voltage;
-                channel.current = current;
-                channel.update_energy(self.data.last_update)?;
-            }
+#[cfg(windows)]
+fn set_input_buffer_size(port: &serialport::COMPort, bytes: u32) -> Result<(), PoweneticsError> {
+    use std::os::windows::io::AsRawHandle;
 
-            let mut stop = false;
+    // SetupComm also sets the output buffer size, which is plenty for commands
+    let handle = port.as_raw_handle() as winapi::um::winnt::HANDLE;
+    if unsafe { winapi::um::commapi::SetupComm(handle, bytes, 4096) } == 0 {
+        return Err(PoweneticsError::Io(io::Error::last_os_error()));
+    }
 
-            for sub in &mut self.subscriptions {
-                stop = sub
-                    .update(&self.data)
-                    .map_err(|e| PoweneticsError::Subscriber(e))?
-                    || stop;
-            }
+    Ok(())
+}
 
-            if stop {
-                break;
+fn verify_device(path: &str, ports: &[serialport::SerialPortInfo]) -> Result<(), PoweneticsError> {
+    // Resolve symlinks such as /dev/serial/by-id/...
+    let canonical = |name: &str| fs::canonicalize(name).unwrap_or_else(|_| name.into());
+    let port = ports
+        .iter()
+        .find(|port| canonical(&port.port_name) == canonical(path));
+
+    match port.map(|port| &port.port_type) {
+        Some(serialport::SerialPortType::UsbPort(usb)) => {
+            if usb.vid != POWENETICS_USB_VID || usb.pid != POWENETICS_USB_PID {
+                return Err(PoweneticsError::NotPowenetics {
+                    vid: usb.vid,
+                    pid: usb.pid,
+                });
             }
         }
+        Some(port_type) => log::warn!(
+            "Unable to verify that {} is a Powenetics device, port type is {:?}",
+            path,
+            port_type
+        ),
+        None => log::warn!(
+            "Unable to verify that {} is a Powenetics device, port not found",
+            path
+        ),
+    }
 
-        Ok(())
+    Ok(())
+}
+
+fn map_open_error(err: serialport::Error) -> PoweneticsError {
+    // serialport does not expose EBUSY (Unix) or ERROR_ACCESS_DENIED (Windows) as an error kind
+    let description = err.description.to_lowercase();
+
+    if description.contains("busy") || (cfg!(windows) && description.contains("access is denied")) {
+        PoweneticsError::PortBusy(err)
+    } else {
+        PoweneticsError::SerialPort(err)
     }
+}
 
-    pub fn subscribe(&mut self, cb: Box<dyn PoweneticsSubscriber>) {
-        self.subscriptions.push(cb);
+/// Raw readings decoded from a single measurement packet
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ParsedPacket {
+    pub sequence: u16,
+    /// Voltages in mV, in protocol order
+    pub voltages: [u16; POWENETICS_CHANNELS.len()],
+    /// Currents in mA, in protocol order
+    pub currents: [u32; POWENETICS_CHANNELS.len()],
+}
+
+/// Decode a measurement packet, e.g. from a raw capture of the serial stream. Only the header is
+/// checked, energy and timestamps are left to the caller.
+pub fn parse_measurement_packet(
+    buf: &[u8; POWENETICS_MEASUREMENT_PACKET_SIZE],
+) -> Result<ParsedPacket, PoweneticsError> {
+    PacketParser::DEFAULT.parse(buf)
+}
+
+/// Whether `err` indicates that the port has to be reopened. Timeouts are left to the caller, the
+/// device may only be slow to respond.
+fn is_connection_loss(err: &PoweneticsError) -> bool {
+    match err {
+        PoweneticsError::Io(err) => err.kind() != io::ErrorKind::TimedOut,
+        PoweneticsError::SerialPort(err) => {
+            err.kind() != serialport::ErrorKind::Io(io::ErrorKind::TimedOut)
+        }
+        _ => false,
     }
+}
 
-    pub fn data(&self) -> &PoweneticsData {
-        &self.data
+fn channel_names(channels: &[ChannelId]) -> String {
+    channels
+        .iter()
+        .map(|id| id.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Offset of the first packet header in a buffer read from a stream not aligned to packet
+/// boundaries. Where the buffer is long enough, the header must be followed by another header a
+/// packet later to rule out measurement data that looks like a header.
+fn find_packet_start(buf: &[u8]) -> Option<usize> {
+    let header = PacketLayout::DEFAULT.header;
+    let is_header = |offset: usize| buf.get(offset..offset + 2) == Some(&header);
+
+    (1..POWENETICS_MEASUREMENT_PACKET_SIZE.min(buf.len())).find(|&offset| {
+        is_header(offset)
+            && (buf.len() < offset + POWENETICS_MEASUREMENT_PACKET_SIZE + 2
+                || is_header(offset + POWENETICS_MEASUREMENT_PACKET_SIZE))
+    })
+}
+
+pub fn new(path: &str) -> Result<Powenetics, PoweneticsError> {
+    new_with_config(path, PoweneticsConfig::default())
+}
+
+pub fn new_with_config(
+    path: &str,
+    config: PoweneticsConfig,
+) -> Result<Powenetics, PoweneticsError> {
+    PoweneticsBuilder::new().config(config).open(path)
+}
+
+/// Return the first of the given devices that sends a well-formed measurement packet when
+/// started, closing all others. Devices failing with an error are skipped as well.
+pub fn select_responsive<I>(candidates: I) -> Result<Powenetics, PoweneticsError>
+where
+    I: IntoIterator<Item = Powenetics>,
+{
+    for mut candidate in candidates {
+        match candidate.responds() {
+            Ok(true) => return Ok(candidate),
+            Ok(false) => {}
+            Err(err) => log::warn!("Skipping {}: {}", candidate.port_name, err),
+        }
+    }
+
+    Err(PoweneticsError::NoDeviceFound)
+}
+
+/// Open the serial ports that may belong to a Powenetics device (USB ports with the Powenetics
+/// VID and PID as well as ports of unknown type) and return the first one that responds, see
+/// `select_responsive`. Ports that cannot be opened are skipped.
+pub fn detect() -> Result<Powenetics, PoweneticsError> {
+    let candidates = serialport::available_ports()?
+        .into_iter()
+        .filter(|port| match &port.port_type {
+            serialport::SerialPortType::UsbPort(usb) => {
+                usb.vid == POWENETICS_USB_VID && usb.pid == POWENETICS_USB_PID
+            }
+            serialport::SerialPortType::Unknown => true,
+            _ => false,
+        })
+        .filter_map(|port| match new(&port.port_name) {
+            Ok(p) => Some(p),
+            Err(err) => {
+                log::warn!("Skipping {}: {}", port.port_name, err);
+                None
+            }
+        });
+
+    select_responsive(candidates)
+}
+
+/// Names of all USB serial ports with the Powenetics VID and PID
+pub fn find_all_ports() -> Result<Vec<String>, PoweneticsError> {
+    Ok(serialport::available_ports()?
+        .into_iter()
+        .filter(|port| {
+            matches!(&port.port_type, serialport::SerialPortType::UsbPort(usb)
+                if usb.vid == POWENETICS_USB_VID && usb.pid == POWENETICS_USB_PID)
+        })
+        .map(|port| port.port_name)
+        .collect())
+}
+
+/// Name of the first USB serial port with the Powenetics VID and PID, see `find_all_ports` where
+/// multiple devices may be connected
+pub fn find_port() -> Result<Option<String>, PoweneticsError> {
+    Ok(find_all_ports()?.into_iter().next())
+}
+
+/// Open the port returned by `find_port`, failing with `PoweneticsError::NoDeviceFound` if there
+/// is none. Unlike `detect`, ports not identified as Powenetics devices are not tried.
+pub fn open_auto() -> Result<Powenetics, PoweneticsError> {
+    new(&find_port()?.ok_or(PoweneticsError::NoDeviceFound)?)
+}
+
+/// Create a `Powenetics` communicating through an already opened (or emulated) serial port
+pub fn from_port(port: Box<dyn SerialPort>, port_name: &str) -> Powenetics {
+    Powenetics {
+        port,
+        port_name: String::from(port_name),
+        port_settings: None,
+        started: false,
+        preserve_energy: false,
+        start_retries: POWENETICS_DEFAULT_START_RETRIES,
+        calibration_echo: false,
+        banner: None,
+        calibration: [None; POWENETICS_CHANNELS.len()],
+        metadata: None,
+        link: LinkCounters::default(),
+        io: IoStats::default(),
+        pulling: false,
+        pull_sequence: None,
+        virtual_channel_fns: vec![],
+        off_threshold: None,
+        integration: IntegrationMethod::Rectangular,
+        monotonic_clock: false,
+        measurement_start: None,
+        history: VecDeque::new(),
+        history_capacity: 0,
+        max_drop_rate: None,
+        drop_window: DropWindow::default(),
+        error_recovery: false,
+        stop_requested: Arc::new(AtomicBool::new(false)),
+        stop_reason: None,
+        data: PoweneticsData::new(),
+        subscriptions: vec![],
+        next_subscription_id: 0,
+        samples_processed: 0,
+    }
+}
+
+impl PoweneticsData {
+    pub(crate) fn new() -> Self {
+        let channels = array::from_fn(|i| Channel {
+            name: String::from(POWENETICS_CHANNELS[i]),
+            id: i as u8,
+            voltage: 0,
+            current: 0,
+            power: 0,
+            previous_power: 0,
+            energy: 0,
+            energy_secondary: 0,
+            quantization_error: 0,
+            last_update: time::SystemTime::UNIX_EPOCH,
+        });
+
+        PoweneticsData {
+            channels,
+            last_update: time::SystemTime::UNIX_EPOCH,
+            annotations: vec![],
+            excluded_from_totals: [false; POWENETICS_CHANNELS.len()],
+            virtual_channels: vec![],
+            off_energy: 0,
+            sample_index: 0,
+            elapsed: time::Duration::ZERO,
+            sequence: 0,
+            dropped_samples: 0,
+        }
+    }
+
+    pub fn channel_by_id(&self, id: usize) -> Result<&Channel, PoweneticsError> {
+        if id >= self.channels.len() {
+            return Err(PoweneticsError::InvalidChannel);
+        }
+
+        Ok(&self.channels[id])
+    }
+
+    pub fn channel(&self, id: ChannelId) -> &Channel {
+        &self.channels[id as usize]
+    }
+
+    /// Look up a channel by its name, see `Powenetics::set_channel_name`, or else by its default
+    /// name as in `POWENETICS_CHANNELS`
+    pub fn channel_by_name(&self, name: &str) -> Result<&Channel, PoweneticsError> {
+        if let Some(channel) = self.channels.iter().find(|ch| ch.name == name) {
+            return Ok(channel);
+        }
+
+        for (i, n) in POWENETICS_CHANNELS.iter().enumerate() {
+            if *n == name {
+                return Ok(&self.channels[i]);
+            }
+        }
+
+        Err(PoweneticsError::InvalidChannel)
+    }
+
+    pub fn channels(&self) -> &[Channel; POWENETICS_CHANNELS.len()] {
+        &self.channels
+    }
+
+    /// Channels in the given order for display, `Channel` itself is deliberately not ordered
+    pub fn channels_ordered(&self, order: ChannelOrder) -> Vec<&Channel> {
+        let mut channels: Vec<&Channel> = self.channels.iter().collect();
+
+        match order {
+            ChannelOrder::Protocol => {}
+            ChannelOrder::NominalVoltage => {
+                channels.sort_by_key(|c| c.channel_id().nominal_voltage())
+            }
+            ChannelOrder::Group => channels.sort_by_key(|c| c.channel_id().display_rank()),
+            ChannelOrder::Power => channels.sort_by_key(|c| cmp::Reverse(c.power())),
+        }
+
+        channels
+    }
+
+    /// Wall-clock reception time of the sample
+    pub fn last_update(&self) -> time::SystemTime {
+        self.last_update
+    }
+
+    /// Monotonic time since the first sample of the measurement, unaffected by adjustments of
+    /// the system clock
+    pub fn elapsed(&self) -> time::Duration {
+        self.elapsed
+    }
+
+    /// Sequence number of the packet the sample was decoded from, as counted by the device
+    pub fn sequence(&self) -> u16 {
+        self.sequence
+    }
+
+    /// Number of samples missing from the measurement so far according to gaps in the sequence,
+    /// which are only tolerated with `set_max_drop_rate` or `set_error_recovery`. Gaps across a
+    /// resynchronization after a corrupted header or a device reset are not counted.
+    pub fn dropped_samples(&self) -> u64 {
+        self.dropped_samples
+    }
+
+    /// Markers requested via `ControlFlow::Annotate` while processing the previous sample
+    pub fn annotations(&self) -> &[String] {
+        &self.annotations
+    }
+
+    fn channels_in_totals(&self) -> impl Iterator<Item = &Channel> {
+        self.channels
+            .iter()
+            .filter(|ch| !self.excluded_from_totals[ch.id as usize])
+    }
+
+    /// Sum of the power of all channels not excluded from totals in µW
+    pub fn total_power(&self) -> u64 {
+        self.channels_in_totals().map(|ch| ch.power() as u64).sum()
+    }
+
+    /// Sum of the energy of all channels not excluded from totals in nJ
+    pub fn total_energy(&self) -> u64 {
+        self.channels_in_totals().map(|ch| ch.energy).sum()
+    }
+
+    /// Sum of the power of all channels of a group in µW. Exclusions from totals do not apply.
+    pub fn power_for_group(&self, group: ChannelGroup) -> u64 {
+        self.channels
+            .iter()
+            .filter(|ch| ch.channel_id().group() == group)
+            .map(|ch| ch.power() as u64)
+            .sum()
+    }
+
+    /// Power drawn through the ATX 24-pin connector in µW, see `power_for_group`
+    pub fn atx_power(&self) -> u64 {
+        self.power_for_group(ChannelGroup::Atx)
+    }
+
+    /// Power drawn through the EPS connectors in µW, typically the CPU power
+    pub fn eps_power(&self) -> u64 {
+        self.power_for_group(ChannelGroup::Eps)
+    }
+
+    /// Power drawn through the PCIe auxiliary connectors and the PCIe slot in µW, typically the
+    /// GPU power
+    pub fn pcie_power(&self) -> u64 {
+        self.power_for_group(ChannelGroup::Pcie) + self.power_for_group(ChannelGroup::PcieSlot)
+    }
+
+    /// Sum of the current of the named channels in mA, e.g. to total the PCIe rails. Exclusions
+    /// from totals do not apply to channels named explicitly.
+    pub fn total_current_on(&self, names: &[&str]) -> Result<u64, PoweneticsError> {
+        names.iter().try_fold(0, |total, name| {
+            Ok(total + self.channel_by_name(name)?.current() as u64)
+        })
+    }
+
+    /// Return the energy of each channel in nJ and reset it to zero in one step.
+    ///
+    /// Subscribers, which only see the data immutably, get the same effect by reading the energy
+    /// and returning `ControlFlow::ResetEnergy`, as no sample is processed in between.
+    pub fn take_energy(&mut self) -> [u64; POWENETICS_CHANNELS.len()] {
+        for channel in &mut self.virtual_channels {
+            channel.reset_energy();
+        }
+
+        array::from_fn(|i| {
+            let energy = self.channels[i].energy;
+            self.channels[i].reset_energy();
+            energy
+        })
+    }
+
+    /// Reset the secondary energy of all channels, see `Channel::energy_secondary`
+    pub fn reset_energy_secondary(&mut self) {
+        for channel in &mut self.channels {
+            channel.reset_energy_secondary();
+        }
+    }
+
+    /// Like `take_energy`, returning `total_energy`
+    pub fn take_total_energy(&mut self) -> u64 {
+        let total = self.total_energy();
+        self.take_energy();
+        total
+    }
+
+    /// Make the next sample only set the reference time for energy integration
+    fn clear_timestamps(&mut self) {
+        for channel in &mut self.channels {
+            channel.last_update = time::SystemTime::UNIX_EPOCH;
+        }
+
+        for channel in &mut self.virtual_channels {
+            channel.last_update = time::SystemTime::UNIX_EPOCH;
+        }
+    }
+
+    /// Total energy in nJ not integrated while the system was off, see
+    /// `Powenetics::set_off_threshold`. Only reset when starting a measurement without preserving
+    /// energy and by `Powenetics::begin_session`.
+    pub fn off_energy(&self) -> u64 {
+        self.off_energy
+    }
+
+    /// Index of this sample among all samples processed since the device was opened, starting
+    /// at 0. Unlike the packet sequence number, it does not wrap and continues across device
+    /// resets and measurements.
+    pub fn sample_index(&self) -> u64 {
+        self.sample_index
+    }
+
+    /// Channels added with `Powenetics::add_virtual_channel`, in the order they were added
+    pub fn virtual_channels(&self) -> &[VirtualChannel] {
+        &self.virtual_channels
+    }
+
+    pub fn virtual_channel_by_name(&self, name: &str) -> Option<&VirtualChannel> {
+        self.virtual_channels.iter().find(|ch| ch.name() == name)
+    }
+
+    /// Whether the channel is omitted from `total_power` and `total_energy`, see
+    /// `Powenetics::exclude_from_totals`
+    pub fn is_excluded_from_totals(&self, id: ChannelId) -> bool {
+        self.excluded_from_totals[id as usize]
+    }
+
+    /// Cheap hash over the voltages and currents of all channels for detecting changes between
+    /// samples. Energy and timestamps are not included.
+    pub fn checksum(&self) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf29ce484222325;
+
+        for ch in &self.channels {
+            for byte in ch
+                .voltage
+                .to_le_bytes()
+                .into_iter()
+                .chain(ch.current.to_le_bytes())
+            {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+
+        hash
+    }
+
+    /// Copy of the timestamp, sample index and per-channel readings that outlives `update`
+    pub fn snapshot(&self) -> OwnedSample {
+        OwnedSample {
+            last_update: self.last_update,
+            elapsed: self.elapsed,
+            sample_index: self.sample_index,
+            voltages: array::from_fn(|i| self.channels[i].voltage),
+            currents: array::from_fn(|i| self.channels[i].current),
+            energies: array::from_fn(|i| self.channels[i].energy),
+        }
+    }
+
+    /// Overwrite the timestamp, sample index and per-channel readings with those of `sample`
+    pub(crate) fn apply(&mut self, sample: &OwnedSample) {
+        self.last_update = sample.last_update;
+        self.elapsed = sample.elapsed;
+        self.sample_index = sample.sample_index;
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            channel.set_reading(sample.voltages[i], sample.currents[i]);
+            channel.energy = sample.energies[i];
+            channel.last_update = sample.last_update;
+        }
+    }
+}
+
+impl Powenetics {
+    /// Calibrate a channel against a reference current in mA flowing through it at this moment.
+    ///
+    /// The device trims the channel so that it reads the reference. It has no command to read
+    /// its calibration back, see `calibration_status` for the references applied from this host.
+    /// As a calibration only holds for the current flowing while it is applied, stored
+    /// references cannot be restored in a later session.
+    pub fn calibrate(&mut self, channel: &Channel, reference: u32) -> Result<(), PoweneticsError> {
+        self.send_calibration(channel.id, reference)
+    }
+
+    pub fn calibrate_channel(
+        &mut self,
+        id: ChannelId,
+        reference: u32,
+    ) -> Result<(), PoweneticsError> {
+        self.send_calibration(id.as_u8(), reference)
+    }
+
+    /// Like `calibrate`, with the channel given by its custom or default name
+    pub fn calibrate_by_name(&mut self, name: &str, reference: u32) -> Result<(), PoweneticsError> {
+        let id = self.data.channel_by_name(name)?.channel_id();
+
+        self.calibrate_channel(id, reference)
+    }
+
+    /// Like `calibrate`, with the channel given by its numeric ID
+    pub fn calibrate_by_id(&mut self, id: u8, reference: u32) -> Result<(), PoweneticsError> {
+        self.send_calibration(id, reference)
+    }
+
+    /// Calibrate every channel with a nonzero reference in mA, given in protocol order, e.g. with
+    /// a known load applied to several rails at once.
+    ///
+    /// Channels without power do not stop the calibration of the remaining channels, they are
+    /// reported together as `PoweneticsError::NoPowerOnChannels` at the end. Other errors stop
+    /// the calibration immediately.
+    pub fn calibrate_all(
+        &mut self,
+        references: &[u32; POWENETICS_CHANNELS.len()],
+    ) -> Result<(), PoweneticsError> {
+        let mut unpowered = vec![];
+
+        for (id, &reference) in ChannelId::ALL.iter().zip(references) {
+            if reference == 0 {
+                continue;
+            }
+
+            match self.calibrate_channel(*id, reference) {
+                Ok(()) => {}
+                Err(PoweneticsError::NoPowerOnChannel) => unpowered.push(*id),
+                Err(err) => return Err(err),
+            }
+        }
+
+        if !unpowered.is_empty() {
+            return Err(PoweneticsError::NoPowerOnChannels {
+                channels: unpowered,
+            });
+        }
+
+        Ok(())
+    }
+
+    fn send_calibration(&mut self, id: u8, reference: u32) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        if id as usize >= POWENETICS_CHANNELS.len() {
+            return Err(PoweneticsError::InvalidChannel);
+        }
+
+        let bytes = reference.to_be_bytes();
+        let command = [0xCA, id, bytes[1], bytes[2], bytes[3]];
+        let expected_response_len = if self.calibration_echo {
+            command.len()
+        } else {
+            2
+        };
+        let response =
+            self.send_command(&command, expected_response_len)
+                .map_err(|err| match err {
+                    PoweneticsError::CommandResponse(err) => {
+                        PoweneticsError::CalibrationResponse(err)
+                    }
+                    err => err,
+                })?;
+
+        if self.calibration_echo && response.len() == command.len() {
+            if response != command {
+                return Err(PoweneticsError::Protocol {
+                    message: format!(
+                        "calibration echo mismatch, sent {:02X?}, received {:02X?}",
+                        command, response
+                    ),
+                });
+            }
+        } else if !response.is_empty() {
+            // The stock firmware only responds if calibration failed
+            let bytes_to_read = response.len() as u32
+                + self
+                    .port
+                    .bytes_to_read()
+                    .map_err(PoweneticsError::CalibrationResponse)?;
+
+            if bytes_to_read != 2 {
+                return Err(PoweneticsError::Protocol {
+                    message: format!("expected 2 bytes, received {}", bytes_to_read),
+                });
+            } else if response == [0xCA, 0xAC] {
+                return Err(PoweneticsError::NoPowerOnChannel);
+            } else {
+                return Err(PoweneticsError::Protocol {
+                    message: format!(
+                        "expected [0xCA, 0xAC], received [{:#04X}, {:#04X}]",
+                        response[0], response[1]
+                    ),
+                });
+            }
+        } else if self.calibration_echo {
+            return Err(PoweneticsError::Protocol {
+                message: String::from("calibration was not echoed"),
+            });
+        }
+
+        self.calibration[id as usize] = Some(reference);
+
+        Ok(())
+    }
+
+    /// Send a raw command to the device and wait for up to `expected_response_len` bytes of
+    /// response.
+    ///
+    /// Returns as soon as the expected number of bytes is available, but waits at most 1 ms. The
+    /// response may thus be shorter than expected, or empty if the device did not respond at all
+    /// (many commands are only answered in case of an error). Commands not expecting a response
+    /// always wait the full timeout to give the device time to process them.
+    pub fn send_command(
+        &mut self,
+        cmd: &[u8],
+        expected_response_len: usize,
+    ) -> Result<Vec<u8>, PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        self.transmit_command(cmd, expected_response_len)
+    }
+
+    /// `send_command` without checking whether a measurement runs
+    fn transmit_command(
+        &mut self,
+        cmd: &[u8],
+        expected_response_len: usize,
+    ) -> Result<Vec<u8>, PoweneticsError> {
+        self.write_port(cmd)?;
+
+        let deadline = time::Instant::now() + POWENETICS_COMMAND_TIMEOUT;
+        let available = loop {
+            let available = self
+                .port
+                .bytes_to_read()
+                .map_err(PoweneticsError::CommandResponse)? as usize;
+
+            if (expected_response_len != 0 && available >= expected_response_len)
+                || time::Instant::now() >= deadline
+            {
+                break available;
+            }
+
+            thread::sleep(POWENETICS_COMMAND_POLL_INTERVAL);
+        };
+
+        let mut response = vec![0; available.min(expected_response_len)];
+        self.read_port(&mut response)?;
+
+        Ok(response)
+    }
+
+    pub fn begin_calibration(
+        &mut self,
+    ) -> Result<calibration::CalibrationBatch<'_>, PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        Ok(calibration::CalibrationBatch::new(self))
+    }
+
+    /// Restore the default calibration of all channels.
+    ///
+    /// The device may reboot upon the reset, announcing itself with its ready message again. To
+    /// leave the device in a known state, this waits up to 500 ms for the ready message and
+    /// consumes it. If the device keeps streaming measurement packets instead, the stream is
+    /// resynchronized when the next measurement is started.
+    pub fn reset_calibration(&mut self) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        self.send_command(&[0xCA, 0xAC, 0xBD, 0x00], 0)?;
+
+        self.calibration = [None; POWENETICS_CHANNELS.len()];
+
+        let deadline = time::Instant::now() + POWENETICS_RESET_TIMEOUT;
+        while time::Instant::now() < deadline {
+            if self.port.bytes_to_read()? as usize >= POWENETICS_READY_MESSAGE.len() {
+                let mut buf = [0; POWENETICS_READY_MESSAGE.len()];
+                self.read_port(&mut buf)?;
+
+                if buf == POWENETICS_READY_MESSAGE.as_bytes() {
+                    self.banner = Some(String::from(POWENETICS_READY_MESSAGE));
+                }
+
+                break;
+            }
+
+            thread::sleep(POWENETICS_COMMAND_POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+
+    /// Apply the calibrations sent so far, also done implicitly when starting a measurement
+    pub fn finalize_calibration(&mut self) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        self.send_command(&[0xCA, 0xAC, 0xBD, 0x01], 0)?;
+
+        Ok(())
+    }
+
+    /// Start the measurement and process packets until a subscriber returns `ControlFlow::Stop`
+    /// or an error occurs.
+    ///
+    /// The device has no command to stop or pause streaming. Stopping only ends processing on the
+    /// host while the device keeps sending packets, which are discarded when the measurement is
+    /// started again: the sequence is re-seeded and energy is only integrated from the first new
+    /// sample on (see `set_preserve_energy_across_restart`). A pause is thus a stop followed by a
+    /// restart with energy preserved. Only unplugging the device fully stops it, after which it
+    /// sends its ready message again.
+    pub fn start_measurement(&mut self) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        self.pulling = false;
+        self.prepare_stream()?;
+
+        for channel in &mut self.data.channels {
+            if !self.preserve_energy {
+                channel.reset_energy();
+                channel.reset_energy_secondary();
+            }
+
+            // Never integrate over the time the measurement was not running
+            channel.last_update = time::SystemTime::UNIX_EPOCH;
+        }
+
+        for channel in &mut self.data.virtual_channels {
+            if !self.preserve_energy {
+                channel.reset_energy();
+            }
+
+            channel.last_update = time::SystemTime::UNIX_EPOCH;
+        }
+
+        if !self.preserve_energy {
+            self.data.off_energy = 0;
+        }
+
+        let calibrated: Vec<u8> = (0..POWENETICS_CHANNELS.len() as u8)
+            .filter(|&id| self.calibration[id as usize].is_some())
+            .collect();
+        let metadata = RunMetadata::new(self.banner.clone(), &self.port_name, &calibrated);
+
+        for sub in &mut self.subscriptions {
+            sub.subscriber
+                .begin(&metadata, &self.data)
+                .map_err(PoweneticsError::Subscriber)?;
+        }
+
+        self.metadata = Some(metadata);
+        self.stop_reason = None;
+
+        self.start_stream()?;
+
+        self.started = true;
+        self.link.restart();
+        self.measurement_start = None;
+        self.data.dropped_samples = 0;
+        self.drop_window = DropWindow::default();
+        let result = self.wait();
+        self.started = false;
+
+        result
+    }
+
+    /// Read packets from the device for a short while to estimate its sample period. `packets`
+    /// (at least two) packets are read.
+    ///
+    /// As the device cannot be stopped, it keeps streaming afterwards. This does not affect a
+    /// subsequent `start_measurement`.
+    pub fn estimate_sample_period(
+        &mut self,
+        packets: usize,
+    ) -> Result<SamplePeriod, PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        self.prepare_stream()?;
+        self.send_start_command()?;
+
+        let mut times = Vec::with_capacity(packets.max(2));
+        for _ in 0..packets.max(2) {
+            let mut buf = [0; POWENETICS_MEASUREMENT_PACKET_SIZE];
+
+            self.read_port(&mut buf)?;
+            times.push(time::Instant::now());
+
+            if buf[..2] != [0xCA, 0xAC] {
+                return Err(PoweneticsError::Protocol {
+                    message: format!(
+                        "expected [0xCA, 0xAC], received [{:#04X}, {:#04X}]",
+                        buf[0], buf[1]
+                    ),
+                });
+            }
+        }
+
+        let intervals: Vec<f64> = times
+            .windows(2)
+            .map(|w| (w[1] - w[0]).as_secs_f64())
+            .collect();
+        let mean = intervals.iter().sum::<f64>() / intervals.len() as f64;
+        let variance =
+            intervals.iter().map(|i| (i - mean).powi(2)).sum::<f64>() / intervals.len() as f64;
+
+        Ok(SamplePeriod {
+            mean: time::Duration::from_secs_f64(mean),
+            variance,
+        })
+    }
+
+    /// Open the device at `path`, start it, and check whether it sends a well-formed measurement
+    /// packet. Errors are only returned if the port cannot be opened or used at all.
+    ///
+    /// The protocol offers no way to stop a running measurement, so the device keeps streaming
+    /// after the port has been closed. This does not affect subsequent measurements.
+    pub fn quick_check(path: &str) -> Result<bool, PoweneticsError> {
+        new(path)?.responds()
+    }
+
+    fn responds(&mut self) -> Result<bool, PoweneticsError> {
+        match self.prepare_stream() {
+            Err(PoweneticsError::Protocol { .. }) => return Ok(false),
+            result => result?,
+        }
+
+        self.send_start_command()?;
+
+        let mut buf = [0; POWENETICS_MEASUREMENT_PACKET_SIZE];
+        match self.read_port(&mut buf) {
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => return Ok(false),
+            result => result?,
+        }
+
+        Ok(buf[..2] == [0xCA, 0xAC])
+    }
+
+    /// Finalize calibration and consume anything the device sent before measurement starts
+    fn prepare_stream(&mut self) -> Result<(), PoweneticsError> {
+        self.finalize_calibration()?;
+
+        let bytes_to_read = self.port.bytes_to_read()? as usize;
+        if bytes_to_read != 0 {
+            // Make sure we only read whole packets here, or just the ready message of a device
+            // that has not streamed yet. Anything shorter is left to the packet reader.
+            let len = match bytes_to_read / POWENETICS_MEASUREMENT_PACKET_SIZE {
+                0 if bytes_to_read == POWENETICS_READY_MESSAGE.len() => bytes_to_read,
+                0 => return Ok(()),
+                packets => packets * POWENETICS_MEASUREMENT_PACKET_SIZE,
+            };
+            let mut buf = vec![0; len];
+
+            self.read_port(&mut buf)?;
+
+            // Upon plugging in, the PMD will send its ready message, however, there is no way to
+            // explicitly stop the measurement other than physically unplugging the device. If the
+            // measurement was aborted and is now restarted without replugging, we will not receive
+            // a ready message but a continuing stream of measurement packets.
+            if String::from_utf8_lossy(&buf).starts_with(POWENETICS_READY_MESSAGE) {
+                self.banner = Some(String::from(POWENETICS_READY_MESSAGE));
+            } else if buf[..2] != [0xCA, 0xAC] {
+                // A previous measurement may have ended within a packet (e.g. due to a read
+                // timeout), skip ahead to the next packet boundary.
+                let Some(offset) = find_packet_start(&buf) else {
+                    return Err(PoweneticsError::Protocol {
+                        message: format!(
+                            "expected \"{}\", received {:?}",
+                            POWENETICS_READY_MESSAGE, buf
+                        ),
+                    });
+                };
+
+                self.read_port(&mut vec![0; offset])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_port(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.port.read_exact(buf)?;
+        self.io.bytes_read += buf.len() as u64;
+
+        Ok(())
+    }
+
+    fn write_port(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.port.write_all(buf)?;
+        self.port.flush()?;
+        self.io.bytes_written += buf.len() as u64;
+
+        Ok(())
+    }
+
+    fn send_start_command(&mut self) -> Result<(), PoweneticsError> {
+        // The PMD does not care about further messages even if the measurement is already running,
+        // so this is also used to restart the stream while a measurement runs.
+        self.transmit_command(&POWENETICS_START_COMMAND, 0)?;
+
+        Ok(())
+    }
+
+    /// Send the start command until the device starts streaming
+    fn start_stream(&mut self) -> Result<(), PoweneticsError> {
+        for _ in 0..=self.start_retries {
+            self.send_start_command()?;
+
+            let deadline = time::Instant::now() + POWENETICS_START_TIMEOUT;
+            while time::Instant::now() < deadline {
+                if self.port.bytes_to_read()? != 0 {
+                    return Ok(());
+                }
+
+                thread::sleep(POWENETICS_COMMAND_POLL_INTERVAL);
+            }
+        }
+
+        Err(PoweneticsError::StartTimeout {
+            attempts: self.start_retries + 1,
+        })
+    }
+
+    /// Read the next packet and update the channels, checking its sequence against `expected`
+    /// (unless `None`), which is advanced to the next sequence. Returns false if the device reset
+    /// itself instead of sending a packet.
+    fn read_packet(&mut self, expected: &mut Option<u16>) -> Result<bool, PoweneticsError> {
+        let mut buf = [0; POWENETICS_MEASUREMENT_PACKET_SIZE];
+
+        self.read_port(&mut buf[..2])?;
+
+        if buf[..2] == POWENETICS_READY_MESSAGE.as_bytes()[..2] {
+            self.restart_after_device_reset()?;
+            *expected = None;
+            return Ok(false);
+        }
+
+        if buf[..2] != PacketLayout::DEFAULT.header && self.error_recovery {
+            // Re-seed the sequence from the recovered packet
+            *expected = None;
+
+            if !self.resync(&mut buf[..2])? {
+                return Ok(false);
+            }
+        }
+
+        self.read_port(&mut buf[2..])?;
+        let instant = time::Instant::now();
+        let now = time::SystemTime::now();
+        if now < self.data.last_update {
+            self.link.clock_step_back();
+        }
+
+        let (start_instant, start_time) = *self.measurement_start.get_or_insert((instant, now));
+        self.data.elapsed = instant - start_instant;
+        self.data.last_update = now;
+
+        let integration_time = if self.monotonic_clock {
+            start_time + self.data.elapsed
+        } else {
+            now
+        };
+        self.link.backlog(self.port.bytes_to_read()?);
+
+        let packet = parse_measurement_packet(&buf)?;
+        let sequence_received = packet.sequence;
+
+        let mut dropped = 0;
+
+        if let Some(sequence) = *expected {
+            if sequence != sequence_received {
+                let duplicate = sequence_received == sequence.wrapping_sub(1);
+
+                if duplicate {
+                    self.link.duplicate();
+                } else {
+                    dropped = sequence_received.wrapping_sub(sequence) as u64;
+                    self.link.dropped(dropped);
+                }
+
+                if self.max_drop_rate.is_none() && self.error_recovery {
+                    self.link.resync();
+                } else if self.max_drop_rate.is_none() {
+                    return Err(PoweneticsError::Protocol {
+                        message: format!(
+                            "expected sequence {}, received {}",
+                            sequence, sequence_received
+                        ),
+                    });
+                }
+
+                // Skip the repeated packet or carry on after the gap
+                if duplicate {
+                    return Ok(false);
+                }
+            }
+        }
+
+        if self.max_drop_rate.is_some() {
+            self.drop_window.record(dropped);
+        }
+
+        *expected = Some(sequence_received.wrapping_add(1));
+        self.data.sequence = sequence_received;
+        self.data.dropped_samples += dropped;
+        self.link.packet(self.data.last_update);
+
+        self.data.sample_index = self.samples_processed;
+        self.samples_processed += 1;
+
+        for (i, channel) in self.data.channels.iter_mut().enumerate() {
+            channel.set_reading(packet.voltages[i], packet.currents[i]);
+        }
+
+        let integrate = self
+            .off_threshold
+            .is_none_or(|threshold| self.data.total_power() >= threshold);
+
+        for channel in &mut self.data.channels {
+            let energy = channel.update_energy(integration_time, integrate, self.integration);
+
+            if !integrate && !self.data.excluded_from_totals[channel.id as usize] {
+                self.data.off_energy += energy;
+            }
+        }
+
+        for i in 0..self.virtual_channel_fns.len() {
+            let (voltage, current) = (self.virtual_channel_fns[i])(&self.data);
+            self.data.virtual_channels[i].update(
+                voltage,
+                current,
+                integration_time,
+                integrate,
+                self.integration,
+            );
+        }
+
+        if self.history_capacity != 0 {
+            if self.history.len() == self.history_capacity {
+                self.history.pop_front();
+            }
+
+            self.history.push_back(self.data.snapshot());
+        }
+
+        Ok(true)
+    }
+
+    /// Skip ahead byte by byte until `header` holds the next packet header. Returns false if the
+    /// device reset itself instead, see `read_packet`.
+    fn resync(&mut self, header: &mut [u8]) -> Result<bool, PoweneticsError> {
+        self.link.resync();
+
+        for _ in 0..POWENETICS_RESYNC_LIMIT {
+            header[0] = header[1];
+            self.read_port(&mut header[1..2])?;
+
+            if header[..2] == PacketLayout::DEFAULT.header {
+                return Ok(true);
+            }
+
+            if header[..2] == POWENETICS_READY_MESSAGE.as_bytes()[..2] {
+                self.restart_after_device_reset()?;
+                return Ok(false);
+            }
+        }
+
+        Err(PoweneticsError::Protocol {
+            message: format!(
+                "no packet header found within {} bytes",
+                POWENETICS_RESYNC_LIMIT
+            ),
+        })
+    }
+
+    /// Read the next packet without involving subscribers and store the resulting sample in
+    /// `out`, which is fully overwritten. Nothing is allocated per sample.
+    ///
+    /// The stream is started by the first call, from then on energy is integrated as in
+    /// `start_measurement`. A gap in the sequence results in a protocol error, after which
+    /// reading may be continued. Calling `start_measurement` ends reading this way, a later call
+    /// starts the stream anew.
+    pub fn read_into(&mut self, out: &mut OwnedSample) -> Result<(), PoweneticsError> {
+        self.start_pulling()?;
+        self.pull_packet()?;
+        *out = self.data.snapshot();
+
+        Ok(())
+    }
+
+    /// Read the next packet without involving subscribers like `read_into`, but wait at most for
+    /// the port's timeout for the packet to begin, returning `None` if it did not.
+    pub fn poll(&mut self) -> Result<Option<&PoweneticsData>, PoweneticsError> {
+        self.start_pulling()?;
+
+        let deadline = time::Instant::now() + self.port.timeout();
+        while self.port.bytes_to_read()? == 0 {
+            if time::Instant::now() >= deadline {
+                return Ok(None);
+            }
+
+            thread::sleep(POWENETICS_COMMAND_POLL_INTERVAL);
+        }
+
+        self.pull_packet()?;
+
+        Ok(Some(&self.data))
+    }
+
+    fn start_pulling(&mut self) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        if !self.pulling {
+            self.prepare_stream()?;
+
+            self.data.clear_timestamps();
+            self.start_stream()?;
+            self.link.restart();
+            self.measurement_start = None;
+            self.data.dropped_samples = 0;
+            self.pull_sequence = None;
+            self.pulling = true;
+        }
+
+        Ok(())
+    }
+
+    fn pull_packet(&mut self) -> Result<(), PoweneticsError> {
+        let mut sequence = self.pull_sequence;
+        let result = loop {
+            match self.read_packet(&mut sequence) {
+                Ok(false) => continue,
+                result => break result,
+            }
+        };
+
+        // Re-seed after an error so that reading can be continued
+        self.pull_sequence = if result.is_ok() { sequence } else { None };
+        result?;
+
+        Ok(())
+    }
+
+    /// Like `read_into`, but skip ahead to the most recent complete packet buffered, e.g. for a
+    /// display that should not lag behind when updated less often than samples arrive. Returns
+    /// the latest sample and the number of packets skipped. Skipped packets still count towards
+    /// the energy.
+    pub fn drain_to_latest(&mut self) -> Result<(OwnedSample, usize), PoweneticsError> {
+        let mut sample = OwnedSample::default();
+        self.read_into(&mut sample)?;
+
+        let buffered = self.port.bytes_to_read()? as usize / POWENETICS_MEASUREMENT_PACKET_SIZE;
+        for _ in 0..buffered {
+            self.read_into(&mut sample)?;
+        }
+
+        Ok((sample, buffered))
+    }
+
+    fn wait(&mut self) -> Result<(), PoweneticsError> {
+        if self.subscriptions.is_empty() {
+            return Err(PoweneticsError::NoSubscribers);
+        }
+
+        let mut sequence = None;
+
+        loop {
+            match self.read_packet(&mut sequence) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(err) if is_connection_loss(&err) => {
+                    self.reconnect(err)?;
+                    sequence = None;
+                    continue;
+                }
+                Err(err) => return Err(err),
+            }
+
+            let (stop, reset_energy, unsubscribed) = match self.subscriptions.as_mut_slice() {
+                // Fast path for the common case of a single sink, there are no control flows to combine
+                [only] => {
+                    let flow = only
+                        .subscriber
+                        .update(&self.data)
+                        .map_err(PoweneticsError::Subscriber)?;
+
+                    // Clearing keeps the allocation for the next annotation
+                    self.data.annotations.clear();
+
+                    match flow {
+                        ControlFlow::Continue => (false, false, vec![]),
+                        ControlFlow::Stop => (true, false, vec![]),
+                        ControlFlow::ResetEnergy => (false, true, vec![]),
+                        ControlFlow::Annotate(text) => {
+                            self.data.annotations.push(text);
+                            (false, false, vec![])
+                        }
+                        ControlFlow::Unsubscribe => (false, false, vec![only.id]),
+                    }
+                }
+                subscriptions => {
+                    let mut stop = false;
+                    let mut reset_energy = false;
+                    let mut annotations = vec![];
+                    let mut unsubscribed = vec![];
+
+                    for sub in subscriptions {
+                        match sub
+                            .subscriber
+                            .update(&self.data)
+                            .map_err(PoweneticsError::Subscriber)?
+                        {
+                            ControlFlow::Continue => {}
+                            ControlFlow::Stop => stop = true,
+                            ControlFlow::ResetEnergy => reset_energy = true,
+                            ControlFlow::Annotate(text) => annotations.push(text),
+                            ControlFlow::Unsubscribe => unsubscribed.push(sub.id),
+                        }
+                    }
+
+                    self.data.annotations = annotations;
+                    (stop, reset_energy, unsubscribed)
+                }
+            };
+
+            for id in unsubscribed {
+                self.unsubscribe(id);
+            }
+
+            if reset_energy {
+                for channel in &mut self.data.channels {
+                    channel.reset_energy();
+                }
+
+                for channel in &mut self.data.virtual_channels {
+                    channel.reset_energy();
+                }
+            }
+
+            // Consume a stop request so that it does not end the next measurement as well
+            let reason = if self.stop_requested.swap(false, Ordering::Relaxed) {
+                StopReason::Requested
+            } else if self
+                .max_drop_rate
+                .is_some_and(|max| self.drop_window.rate() > max)
+            {
+                StopReason::ExcessiveDrops
+            } else if stop {
+                StopReason::Subscriber
+            } else if self.subscriptions.is_empty() {
+                StopReason::NoSubscribers
+            } else {
+                continue;
+            };
+
+            for sub in &mut self.subscriptions {
+                sub.subscriber
+                    .end(reason, &self.data)
+                    .map_err(PoweneticsError::Subscriber)?;
+            }
+
+            self.stop_reason = Some(reason);
+            return Ok(());
+        }
+    }
+
+    /// Reopen the port after `err` occurred during a measurement and restart the stream, see
+    /// `PoweneticsConfig::reconnect_attempts`. Returns `err` if reconnecting is disabled or all
+    /// attempts failed.
+    fn reconnect(&mut self, err: PoweneticsError) -> Result<(), PoweneticsError> {
+        let Some(settings) = self
+            .port_settings
+            .filter(|settings| settings.config.reconnect_attempts != 0)
+        else {
+            return Err(err);
+        };
+
+        log::warn!("Lost connection to {}: {}", self.port_name, err);
+
+        // Close the port first, it cannot be opened again while held exclusively
+        self.port = Box::new(emulated::DisconnectedPort);
+
+        let mut backoff = POWENETICS_RECONNECT_BACKOFF;
+
+        for attempt in 1..=settings.config.reconnect_attempts {
+            thread::sleep(backoff);
+            backoff = cmp::min(backoff * 2, POWENETICS_RECONNECT_MAX_BACKOFF);
+
+            let result = open_port(&self.port_name, &settings).and_then(|port| {
+                self.port = port;
+                // Whatever the device sent before the start command, e.g. its ready message
+                self.port.clear(ClearBuffer::Input)?;
+                self.start_stream()
+            });
+
+            match result {
+                Ok(()) => {
+                    log::warn!(
+                        "Reconnected to {} after {} attempt(s)",
+                        self.port_name,
+                        attempt
+                    );
+
+                    self.link.reconnect();
+                    self.link.restart();
+
+                    self.data.clear_timestamps();
+                    self.data
+                        .annotations
+                        .push(String::from(RECONNECT_ANNOTATION));
+
+                    return Ok(());
+                }
+                Err(err) => log::info!("Reconnect attempt {} failed: {}", attempt, err),
+            }
+        }
+
+        Err(err)
+    }
+
+    /// The device re-sends its ready message after resetting (e.g. due to a brownout) and waits for
+    /// the start command again. The interruption is not integrated into the energy, and the next
+    /// sample is annotated with `DEVICE_RESET_ANNOTATION`.
+    fn restart_after_device_reset(&mut self) -> Result<(), PoweneticsError> {
+        let mut buf = [0; POWENETICS_READY_MESSAGE.len() - 2];
+        self.read_port(&mut buf)?;
+
+        if buf != POWENETICS_READY_MESSAGE.as_bytes()[2..] {
+            return Err(PoweneticsError::Protocol {
+                message: format!(
+                    "expected [0xCA, 0xAC] or \"{}\", received {:?}",
+                    POWENETICS_READY_MESSAGE, buf
+                ),
+            });
+        }
+
+        // Discard anything trailing the ready message
+        let trailing = self.port.bytes_to_read()?;
+        if trailing != 0 {
+            self.read_port(&mut vec![0; trailing as usize])?;
+        }
+
+        self.banner = Some(String::from(POWENETICS_READY_MESSAGE));
+        self.link.device_reset();
+        self.link.restart();
+
+        self.data.clear_timestamps();
+        self.data
+            .annotations
+            .push(String::from(DEVICE_RESET_ANNOTATION));
+
+        self.send_start_command()
+    }
+
+    /// Keep accumulating energy when the measurement is started again after it has stopped
+    /// instead of resetting it to zero (the default). In both modes, energy is only integrated
+    /// while the measurement is running: the first sample after a restart only sets the reference
+    /// time for each channel.
+    pub fn set_preserve_energy_across_restart(&mut self, preserve: bool) {
+        self.preserve_energy = preserve;
+    }
+
+    /// Prepare the next measurement on this instance as a fresh session.
+    ///
+    /// `reset_energy` zeroes the primary and secondary energy and the quantization error of all
+    /// channels, which is useful together with `set_preserve_energy_across_restart(true)` to
+    /// decide per run whether energy carries over. `reset_stats` clears the link health counters
+    /// (`health`, and thus the statistics in `report`) and the port I/O statistics. The sequence
+    /// expectation is re-seeded by every `start_measurement` regardless, and subscribers are
+    /// notified through `begin` as usual.
+    pub fn begin_session(
+        &mut self,
+        reset_energy: bool,
+        reset_stats: bool,
+    ) -> Result<(), PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        if reset_energy {
+            for channel in &mut self.data.channels {
+                channel.reset_energy();
+                channel.reset_energy_secondary();
+            }
+
+            for channel in &mut self.data.virtual_channels {
+                channel.reset_energy();
+            }
+
+            self.data.off_energy = 0;
+        }
+
+        if reset_stats {
+            self.link = LinkCounters::default();
+            self.io = IoStats::default();
+        }
+
+        Ok(())
+    }
+
+    /// Pause energy integration of all channels while total power is below `threshold_watts`,
+    /// e.g. to exclude the standby energy while the system is off. The energy not integrated is
+    /// accounted in `PoweneticsData::off_energy` instead. By default (`None`), energy is always
+    /// integrated, so standby energy is included.
+    pub fn set_off_threshold(&mut self, threshold_watts: Option<f64>) {
+        self.off_threshold = threshold_watts.map(|watts| (watts * 1_000_000.0) as u64);
+    }
+
+    /// Choose how energy is integrated from consecutive samples, `IntegrationMethod::Rectangular`
+    /// by default. Applies to measured and virtual channels alike.
+    pub fn set_integration_method(&mut self, method: IntegrationMethod) {
+        self.integration = method;
+    }
+
+    /// Integrate energy over the intervals of the monotonic clock behind
+    /// `PoweneticsData::elapsed` rather than the system clock, so that adjustments of the latter
+    /// (e.g. by NTP) cannot distort energy readings. Sample timestamps stay wall-clock times.
+    /// Disabled by default.
+    pub fn set_monotonic_clock(&mut self, enabled: bool) {
+        self.monotonic_clock = enabled;
+    }
+
+    /// Recover from corrupted packets instead of failing with a protocol error. On a header
+    /// mismatch, the stream is scanned for the next packet header, and on a sequence mismatch,
+    /// the sequence is re-seeded from the received packet; both count as a resync in
+    /// `LinkHealth::resyncs`. Sequence gaps are still counted as dropped packets. Disabled by
+    /// default, so that any protocol error fails the measurement. With `set_max_drop_rate`, gaps
+    /// are subject to the drop rate limit instead.
+    pub fn set_error_recovery(&mut self, enabled: bool) {
+        self.error_recovery = enabled;
+    }
+
+    /// Tolerate gaps in the packet sequence instead of failing with a protocol error, as long as
+    /// the fraction of packets dropped among the last 1000 packets stays at or below `max_rate`.
+    /// Beyond that, the measurement stops with `StopReason::ExcessiveDrops`. Repeated packets are
+    /// skipped. By default (`None`), any gap or repeated packet fails the measurement.
+    pub fn set_max_drop_rate(&mut self, max_rate: Option<f64>) {
+        self.max_drop_rate = max_rate;
+    }
+
+    /// Add a channel computed from the measured channels for every sample, e.g. the sum of all
+    /// 12V rails (with voltage and current to be returned in mV and mA). The function is called
+    /// after the measured channels have been updated, virtual channels added earlier are already
+    /// updated as well. Virtual channels are available through `PoweneticsData::virtual_channels`.
+    pub fn add_virtual_channel<F>(&mut self, name: &str, f: F)
+    where
+        F: FnMut(&PoweneticsData) -> (u16, u32) + Send + 'static,
+    {
+        self.data.virtual_channels.push(VirtualChannel::new(name));
+        self.virtual_channel_fns.push(Box::new(f));
+    }
+
+    /// Expect the device to echo calibration commands and fail with a protocol error if the echo
+    /// differs from what was sent (e.g. a truncated reference). Disabled by default, as the stock
+    /// firmware does not echo; only enable this for firmware that does.
+    pub fn set_calibration_echo(&mut self, enabled: bool) {
+        self.calibration_echo = enabled;
+    }
+
+    /// Why the last measurement ended, `None` if it ended due to an error or none has ended yet
+    pub fn stop_reason(&self) -> Option<StopReason> {
+        self.stop_reason
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(self.stop_requested.clone())
+    }
+
+    pub fn subscribe(&mut self, cb: Box<dyn PoweneticsSubscriber>) -> SubscriptionId {
+        self.subscribe_with_priority(cb, 0)
+    }
+
+    /// Subscribe with a priority other than the default of 0. For every sample, subscribers are
+    /// called in order of descending priority, and in order of subscription within the same
+    /// priority.
+    ///
+    /// A stop requested by a subscriber only takes effect after all subscribers have processed
+    /// the current sample, so every subscriber sees the final sample regardless of priority.
+    /// Priorities matter where subscribers have side effects that depend on each other, e.g. to
+    /// have a logger write the final sample before a subscriber requesting the stop terminates
+    /// the process from within its callback.
+    pub fn subscribe_with_priority(
+        &mut self,
+        cb: Box<dyn PoweneticsSubscriber>,
+        priority: i32,
+    ) -> SubscriptionId {
+        let id = SubscriptionId(self.next_subscription_id);
+        self.next_subscription_id += 1;
+
+        let index = self
+            .subscriptions
+            .partition_point(|sub| sub.priority >= priority);
+
+        self.subscriptions.insert(
+            index,
+            Subscription {
+                id,
+                priority,
+                subscriber: cb,
+            },
+        );
+
+        id
+    }
+
+    /// Remove a subscriber, returning whether it was still subscribed. Subscribers can remove
+    /// themselves during a measurement by returning `ControlFlow::Unsubscribe`.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        match self.subscriptions.iter().position(|sub| sub.id == id) {
+            Some(index) => {
+                self.subscriptions.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Consuming variant of `subscribe` for chaining, e.g.
+    /// `new(path)?.with_subscriber(a).with_subscriber(b).start_measurement()`
+    pub fn with_subscriber(mut self, cb: Box<dyn PoweneticsSubscriber>) -> Self {
+        self.subscribe(cb);
+        self
+    }
+
+    /// Like `with_subscriber`, with the subscriber given as a closure called for every sample
+    pub fn with_subscriber_fn<F>(self, cb: F) -> Self
+    where
+        F: FnMut(&PoweneticsData) -> anyhow::Result<ControlFlow> + Send + 'static,
+    {
+        self.with_subscriber(Box::new(FnSubscriber(cb)))
+    }
+
+    pub fn subscribe_many<I>(&mut self, cbs: I)
+    where
+        I: IntoIterator<Item = Box<dyn PoweneticsSubscriber>>,
+    {
+        for cb in cbs {
+            self.subscribe(cb);
+        }
+    }
+
+    /// Consume this instance and return the underlying serial port, dropping all subscribers and
+    /// data. The device cannot be stopped, so it may still be streaming measurement packets.
+    pub fn into_port(self) -> Box<dyn SerialPort> {
+        self.port
+    }
+
+    pub fn data(&self) -> &PoweneticsData {
+        &self.data
+    }
+
+    /// Mutable access to the data, e.g. for `PoweneticsData::take_energy` between measurements
+    pub fn data_mut(&mut self) -> &mut PoweneticsData {
+        &mut self.data
+    }
+
+    /// Reference (in mA) each channel was last calibrated with since the device was opened or its
+    /// calibration was reset, `None` for channels using the default calibration. This is tracked
+    /// by the host, the device does not report its calibration.
+    pub fn calibration_status(&self) -> [Option<u32>; POWENETICS_CHANNELS.len()] {
+        self.calibration
+    }
+
+    pub fn health(&self) -> LinkHealth {
+        self.link.health()
+    }
+
+    /// Keep the last `capacity` samples in `history`, dropping the oldest ones beyond that. A
+    /// capacity of 0 (the default) disables the history. Memory is allocated as samples arrive,
+    /// not up front.
+    pub fn set_history_capacity(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+
+        let excess = self.history.len().saturating_sub(capacity);
+        self.history.drain(..excess);
+        self.history.shrink_to(capacity);
+    }
+
+    /// Most recent samples, oldest first, see `set_history_capacity`. The history is kept across
+    /// measurements and can be read between calls to `read_into` or `poll`, or once a
+    /// measurement has ended.
+    pub fn history(&self) -> &VecDeque<OwnedSample> {
+        &self.history
+    }
+
+    /// Bytes transferred over the serial port since it was opened or the statistics were reset
+    /// with `begin_session`
+    pub fn port_io_stats(&self) -> IoStats {
+        self.io
+    }
+
+    pub fn report(&self) -> report::Report {
+        report::Report::new(&self.data, self.link.health())
+    }
+
+    /// Omit the given channels from `PoweneticsData::total_power` and `total_energy`, replacing
+    /// any previous exclusions. Per-channel values are not affected.
+    ///
+    /// Use this when the same power is measured on more than one channel, which depends on how
+    /// the system is wired to the device, e.g. when the ATX12VO standby rail is derived from
+    /// another measured 12V rail, or when a PCIe riser supplies the slot from a measured PCIe
+    /// connector. No channel is excluded by default.
+    pub fn exclude_from_totals(&mut self, channels: &[ChannelId]) {
+        self.data.excluded_from_totals = [false; POWENETICS_CHANNELS.len()];
+
+        for id in channels {
+            self.data.excluded_from_totals[*id as usize] = true;
+        }
+    }
+
+    /// Rename a channel, e.g. to match how the system is wired to the device. The name is
+    /// reported by `Channel::name`, used in output headers and found by
+    /// `PoweneticsData::channel_by_name`, which still accepts the default name as well. Fails with
+    /// `PoweneticsError::InvalidChannel` if there is no channel `id`.
+    pub fn set_channel_name(&mut self, id: u8, name: &str) -> Result<(), PoweneticsError> {
+        let channel = self
+            .data
+            .channels
+            .get_mut(id as usize)
+            .ok_or(PoweneticsError::InvalidChannel)?;
+
+        channel.name = String::from(name);
+        Ok(())
+    }
+
+    /// Metadata of the current run, available once the measurement has been started
+    pub fn run_metadata(&self) -> Option<&RunMetadata> {
+        self.metadata.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Energy(Arc<Mutex<u64>>);
+
+    impl StopSubscriber for Energy {
+        fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<bool> {
+            *self.0.lock().unwrap() = p.channel(ChannelId::ALL[0]).energy();
+            Ok(p.sample_index() >= 4)
+        }
+    }
+
+    #[test]
+    fn bool_subscribers_stop_and_energy_resets() {
+        let port = mock::MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let energy = Arc::new(Mutex::new(0));
+
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(BoolSubscriber(Energy(energy.clone()))))
+            .with_subscriber_fn(|p| {
+                Ok(if p.sample_index() >= 4 {
+                    ControlFlow::ResetEnergy
+                } else {
+                    ControlFlow::Continue
+                })
+            });
+        p.start_measurement().unwrap();
+
+        assert_eq!(p.stop_reason(), Some(StopReason::Subscriber));
+        assert!(*energy.lock().unwrap() > 0);
+        assert_eq!(p.data().channel(ChannelId::ALL[0]).energy(), 0);
+    }
+
+    #[test]
+    fn history_keeps_the_latest_samples() {
+        let port = mock::MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(5)));
+
+        // Must not allocate the whole capacity
+        p.set_history_capacity(usize::MAX);
+        p.set_history_capacity(3);
+        p.start_measurement().unwrap();
+
+        let indices =
+            |p: &Powenetics| -> Vec<u64> { p.history().iter().map(|s| s.sample_index()).collect() };
+        let last = p.data().sample_index();
+
+        assert_eq!(indices(&p), [last - 2, last - 1, last]);
+
+        p.set_history_capacity(1);
+        assert_eq!(indices(&p), [last]);
+    }
+
+    #[test]
+    fn restart_consumes_a_pending_ready_message() {
+        let port = mock::MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .device_reset()
+            .packets(3, [12_000; 13], [1_000; 13]);
+        let annotations = Arc::new(Mutex::new(vec![]));
+        let recorded = annotations.clone();
+
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(3)))
+            .with_subscriber_fn(move |p| {
+                recorded.lock().unwrap().extend_from_slice(p.annotations());
+                Ok(ControlFlow::Continue)
+            });
+
+        p.start_measurement().unwrap();
+        // Only the ready message is pending now
+        p.start_measurement().unwrap();
+
+        assert_eq!(p.data().sequence(), 3);
+        assert!(annotations.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn channel_by_id_checks_range() {
+        let p = from_port(Box::new(mock::MockSerialPort::new()), "mock");
+
+        assert_eq!(
+            p.data().channel_by_id(12).unwrap().name(),
+            POWENETICS_CHANNELS[12]
+        );
+        assert!(matches!(
+            p.data().channel_by_id(13),
+            Err(PoweneticsError::InvalidChannel)
+        ));
+    }
+
+    #[test]
+    fn calibrate_by_custom_name() {
+        let port = mock::MockSerialPort::new();
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock");
+
+        p.set_channel_name(1, "CPU").unwrap();
+        p.calibrate_by_name("CPU", 1000).unwrap();
+        p.calibrate_by_name(POWENETICS_CHANNELS[1], 1000).unwrap();
+
+        assert_eq!(
+            *written.lock().unwrap(),
+            [[0xCA, 1, 0x00, 0x03, 0xE8]; 2].concat()
+        );
+        assert!(matches!(
+            p.calibrate_by_name("GPU", 1000),
+            Err(PoweneticsError::InvalidChannel)
+        ));
+    }
+
+    #[test]
+    fn calibration_response_errors_are_distinct() {
+        let port = mock::MockSerialPort::new().fail_bytes_to_read();
+        let mut p = from_port(Box::new(port), "mock");
+
+        assert!(matches!(
+            p.calibrate_channel(ChannelId::ALL[0], 1000),
+            Err(PoweneticsError::CalibrationResponse(_))
+        ));
+    }
+
+    /// Give every sample a distinct timestamp for energy to be integrated
+    fn slow_down(p: Powenetics) -> Powenetics {
+        p.with_subscriber_fn(|_| {
+            thread::sleep(time::Duration::from_millis(1));
+            Ok(ControlFlow::Continue)
+        })
+    }
+
+    /// Record the total energy each measurement starts with
+    struct Begin(Arc<Mutex<Vec<u64>>>);
+
+    impl PoweneticsSubscriber for Begin {
+        fn begin(&mut self, _metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+            self.0.lock().unwrap().push(p.total_energy());
+            Ok(())
+        }
+
+        fn update(&mut self, _p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    #[test]
+    fn energy_is_preserved_across_restarts_on_request() {
+        for preserve in [false, true] {
+            let port = mock::MockSerialPort::new()
+                .packets(5, [12_000; 13], [1_000; 13])
+                .pause()
+                .packets(5, [12_000; 13], [1_000; 13]);
+            let energy_at_start = Arc::new(Mutex::new(vec![]));
+            let recorded = energy_at_start.clone();
+
+            let mut p = slow_down(
+                from_port(Box::new(port), "mock")
+                    .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(5)))
+                    .with_subscriber(Box::new(Begin(recorded))),
+            );
+            p.set_preserve_energy_across_restart(preserve);
+
+            p.start_measurement().unwrap();
+            let first = p.data().total_energy();
+            p.start_measurement().unwrap();
+
+            assert!(first > 0);
+            if preserve {
+                assert_eq!(*energy_at_start.lock().unwrap(), [0, first]);
+                assert!(p.data().total_energy() > first);
+            } else {
+                assert_eq!(*energy_at_start.lock().unwrap(), [0, 0]);
+            }
+        }
+    }
+
+    #[test]
+    fn responds_only_with_a_well_formed_packet() {
+        let responds = |port: mock::MockSerialPort| from_port(Box::new(port), "mock").responds();
+
+        assert!(responds(mock::MockSerialPort::new().packet([12_000; 13], [1_000; 13])).unwrap());
+        assert!(!responds(mock::MockSerialPort::new().raw(&[0x55; 69])).unwrap());
+        assert!(!responds(mock::MockSerialPort::new().stall().raw(&[0x55; 69])).unwrap());
+    }
+
+    #[test]
+    fn only_powenetics_usb_ports_are_verified() {
+        let path = "/nonexistent/powenetics";
+        let usb = |vid, pid| {
+            vec![serialport::SerialPortInfo {
+                port_name: String::from(path),
+                port_type: serialport::SerialPortType::UsbPort(serialport::UsbPortInfo {
+                    vid,
+                    pid,
+                    serial_number: None,
+                    manufacturer: None,
+                    product: None,
+                }),
+            }]
+        };
+
+        assert!(verify_device(path, &usb(POWENETICS_USB_VID, POWENETICS_USB_PID)).is_ok());
+        assert!(matches!(
+            verify_device(path, &usb(0x1234, POWENETICS_USB_PID)),
+            Err(PoweneticsError::NotPowenetics { vid: 0x1234, .. })
+        ));
+        assert!(matches!(
+            verify_device(path, &usb(POWENETICS_USB_VID, 0x5678)),
+            Err(PoweneticsError::NotPowenetics { pid: 0x5678, .. })
+        ));
+
+        // Ports of other or unknown type are let through with a warning
+        let unknown = vec![serialport::SerialPortInfo {
+            port_name: String::from(path),
+            port_type: serialport::SerialPortType::Unknown,
+        }];
+        assert!(verify_device(path, &unknown).is_ok());
+        assert!(verify_device(path, &[]).is_ok());
+    }
+
+    #[test]
+    fn the_responsive_candidate_is_selected() {
+        let candidates = || {
+            [
+                from_port(Box::new(mock::MockSerialPort::new()), "silent"),
+                from_port(
+                    Box::new(mock::MockSerialPort::new().packets(2, [12_000; 13], [1_000; 13])),
+                    "streaming",
+                ),
+            ]
+        };
+
+        assert_eq!(
+            select_responsive(candidates()).unwrap().port_name,
+            "streaming"
+        );
+        assert_eq!(
+            select_responsive(candidates().into_iter().rev())
+                .unwrap()
+                .port_name,
+            "streaming"
+        );
+        assert!(matches!(
+            select_responsive(candidates().into_iter().take(1)),
+            Err(PoweneticsError::NoDeviceFound)
+        ));
+    }
+
+    fn start_commands(written: &Mutex<Vec<u8>>) -> usize {
+        written
+            .lock()
+            .unwrap()
+            .windows(POWENETICS_START_COMMAND.len())
+            .filter(|w| *w == POWENETICS_START_COMMAND)
+            .count()
+    }
+
+    /// Index, sequence and annotations of every sample
+    type Recording = Arc<Mutex<Vec<(u64, u16, Vec<String>)>>>;
+
+    fn record(p: Powenetics) -> (Powenetics, Recording) {
+        let samples = Arc::new(Mutex::new(vec![]));
+        let recorded = samples.clone();
+
+        let p = p.with_subscriber_fn(move |p| {
+            recorded.lock().unwrap().push((
+                p.sample_index(),
+                p.sequence(),
+                p.annotations().to_vec(),
+            ));
+            Ok(ControlFlow::Continue)
+        });
+
+        (p, samples)
+    }
+
+    #[test]
+    fn mid_stream_banner_restarts_the_stream() {
+        let port = mock::MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .device_reset()
+            .packets(3, [12_000; 13], [1_000; 13]);
+        let written = port.written();
+        let (mut p, samples) = record(
+            from_port(Box::new(port), "mock")
+                .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(6))),
+        );
+
+        p.start_measurement().unwrap();
+
+        let samples = samples.lock().unwrap();
+        let sequences: Vec<u16> = samples.iter().map(|s| s.1).collect();
+
+        assert_eq!(sequences, [1, 2, 3, 1, 2, 3]);
+        assert_eq!(samples[3].2, [DEVICE_RESET_ANNOTATION]);
+        assert_eq!(samples.iter().filter(|s| !s.2.is_empty()).count(), 1);
+        assert_eq!(p.health().device_resets, 1);
+        assert_eq!(start_commands(&written), 2);
+    }
+
+    #[test]
+    fn missed_start_command_is_resent() {
+        let port =
+            mock::MockSerialPort::new()
+                .ignore_starts(1)
+                .packets(3, [12_000; 13], [1_000; 13]);
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(3)));
+
+        p.start_measurement().unwrap();
+
+        assert_eq!(p.data().sequence(), 3);
+        assert_eq!(start_commands(&written), 2);
+    }
+
+    #[test]
+    fn silent_device_times_out_after_all_retries() {
+        let port = mock::MockSerialPort::new().ignore_starts(u32::MAX).packets(
+            3,
+            [12_000; 13],
+            [1_000; 13],
+        );
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(3)));
+
+        assert!(matches!(
+            p.start_measurement(),
+            Err(PoweneticsError::StartTimeout { attempts }) if attempts == POWENETICS_DEFAULT_START_RETRIES + 1
+        ));
+        assert_eq!(
+            start_commands(&written) as u32,
+            POWENETICS_DEFAULT_START_RETRIES + 1
+        );
+    }
+
+    #[test]
+    fn restart_after_stop_mid_packet_realigns() {
+        let packet = |sequence| {
+            PacketLayout::DEFAULT.encode(&ParsedPacket {
+                sequence,
+                voltages: [12_000; 13],
+                currents: [1_000; 13],
+            })
+        };
+        let port = mock::MockSerialPort::new()
+            .raw(&packet(1))
+            .raw(&packet(2))
+            .raw(&packet(3)[..40])
+            .stall()
+            .raw(&packet(3)[40..])
+            .raw(&packet(4))
+            .pause()
+            .raw(&packet(5))
+            .raw(&packet(6));
+        let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+
+        // The read timing out within the third packet ends the first measurement
+        assert!(matches!(
+            p.start_measurement(),
+            Err(PoweneticsError::Io(err)) if err.kind() == io::ErrorKind::TimedOut
+        ));
+
+        p.subscribe(Box::new(limit::SampleCountLimitSubscriber::new(2)));
+        p.start_measurement().unwrap();
+
+        let sequences: Vec<u16> = samples.lock().unwrap().iter().map(|s| s.1).collect();
+        assert_eq!(sequences, [1, 2, 5, 6]);
+    }
+
+    #[test]
+    fn calibration_echo_is_compared() {
+        let command = [0xCA, 0, 0x00, 0x03, 0xE8];
+        let mut truncated = command;
+        truncated[4] = 0;
+
+        let port = mock::MockSerialPort::new()
+            .reply(&command, &command)
+            .reply(&command, &truncated);
+        let mut p = from_port(Box::new(port), "mock");
+        p.set_calibration_echo(true);
+
+        p.calibrate_channel(ChannelId::ALL[0], 1000).unwrap();
+        assert!(matches!(
+            p.calibrate_channel(ChannelId::ALL[0], 1000),
+            Err(PoweneticsError::Protocol { message }) if message.contains("mismatch")
+        ));
+        // Not echoed at all
+        assert!(matches!(
+            p.calibrate_channel(ChannelId::ALL[0], 1000),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+    }
+
+    #[test]
+    fn calibration_reset_consumes_the_ready_message() {
+        let port = mock::MockSerialPort::new().reply(
+            &[0xCA, 0xAC, 0xBD, 0x00],
+            POWENETICS_READY_MESSAGE.as_bytes(),
+        );
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock");
+
+        p.reset_calibration().unwrap();
+
+        assert_eq!(*written.lock().unwrap(), [0xCA, 0xAC, 0xBD, 0x00]);
+        assert_eq!(p.port.bytes_to_read().unwrap(), 0);
+        assert_eq!(p.banner.as_deref(), Some(POWENETICS_READY_MESSAGE));
+    }
+
+    #[test]
+    fn drain_returns_the_latest_buffered_packet() {
+        let port = mock::MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let mut p = from_port(Box::new(port), "mock");
+
+        let (sample, skipped) = p.drain_to_latest().unwrap();
+
+        assert_eq!(skipped, 4);
+        assert_eq!(sample.sample_index(), 4);
+        assert_eq!(p.data().sequence(), 5);
+    }
+
+    #[test]
+    fn off_time_is_accounted_separately() {
+        let mut standby = [0; 13];
+        standby[0] = 100;
+        let port = mock::MockSerialPort::new()
+            .packets(5, [12_000; 13], standby)
+            .packets(5, [12_000; 13], [1_000; 13]);
+        let energies = Arc::new(Mutex::new(vec![]));
+        let recorded = energies.clone();
+
+        let mut p = slow_down(
+            from_port(Box::new(port), "mock")
+                .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(10)))
+                .with_subscriber_fn(move |p| {
+                    recorded.lock().unwrap().push(p.total_energy());
+                    Ok(ControlFlow::Continue)
+                }),
+        );
+
+        // 1.2 W while off, 156 W while on
+        p.set_off_threshold(Some(10.0));
+        p.start_measurement().unwrap();
+
+        let energies = energies.lock().unwrap();
+        assert!(energies[..5].iter().all(|&energy| energy == 0));
+        assert!(energies[9] > 0);
+        assert!(p.data().off_energy() > 0);
+    }
+
+    #[test]
+    fn single_and_multiple_subscribers_behave_alike() {
+        let run = |extra: bool| {
+            let port = mock::MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+            let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+
+            // Registered last, so that the recording subscriber sees its annotation with the
+            // next sample in both cases
+            p.subscribe(Box::new(FnSubscriber(|p: &PoweneticsData| {
+                Ok(match p.sample_index() {
+                    2 => ControlFlow::Annotate(String::from("marker")),
+                    5 => ControlFlow::Stop,
+                    _ => ControlFlow::Continue,
+                })
+            })));
+            if extra {
+                p.subscribe(Box::new(FnSubscriber(|_: &PoweneticsData| {
+                    Ok(ControlFlow::Continue)
+                })));
+            }
+
+            p.start_measurement().unwrap();
+            let samples = samples.lock().unwrap().clone();
+
+            (samples, p.stop_reason())
+        };
+
+        let (samples, reason) = run(false);
+        assert_eq!(samples.len(), 6);
+        assert_eq!(samples[3].2, ["marker"]);
+        assert_eq!(reason, Some(StopReason::Subscriber));
+        assert_eq!((samples, reason), run(true));
+    }
+
+    #[test]
+    fn last_subscriber_unsubscribing_stops_cleanly() {
+        let port = mock::MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let mut p = from_port(Box::new(port), "mock").with_subscriber_fn(|p| {
+            Ok(if p.sample_index() == 2 {
+                ControlFlow::Unsubscribe
+            } else {
+                ControlFlow::Continue
+            })
+        });
+
+        p.start_measurement().unwrap();
+
+        assert_eq!(p.stop_reason(), Some(StopReason::NoSubscribers));
+        assert_eq!(p.data().sample_index(), 2);
+    }
+
+    #[test]
+    fn sample_index_survives_a_sequence_reset() {
+        let port = mock::MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .device_reset()
+            .packets(3, [12_000; 13], [1_000; 13]);
+        let (mut p, samples) = record(
+            from_port(Box::new(port), "mock")
+                .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(6))),
+        );
+
+        p.start_measurement().unwrap();
+
+        let indices: Vec<u64> = samples.lock().unwrap().iter().map(|s| s.0).collect();
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn burst_of_drops_stops_the_measurement() {
+        let port = mock::MockSerialPort::new()
+            .packets(10, [12_000; 13], [1_000; 13])
+            .skip_sequence(5)
+            .packets(10, [12_000; 13], [1_000; 13])
+            .skip_sequence(200)
+            .packets(10, [12_000; 13], [1_000; 13]);
+        let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+
+        p.set_max_drop_rate(Some(0.1));
+        p.start_measurement().unwrap();
+
+        assert_eq!(p.stop_reason(), Some(StopReason::ExcessiveDrops));
+        assert_eq!(samples.lock().unwrap().len(), 21);
+        assert_eq!(p.data().dropped_samples(), 205);
+        assert_eq!(p.health().dropped_packets, 205);
+    }
+
+    #[test]
+    fn sequence_gaps_fail_without_drop_limit() {
+        let port = mock::MockSerialPort::new()
+            .packets(2, [12_000; 13], [1_000; 13])
+            .skip_sequence(1)
+            .packets(2, [12_000; 13], [1_000; 13]);
+        let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+
+        assert!(matches!(
+            p.start_measurement(),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+        assert_eq!(samples.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn corrupted_bytes_are_skipped_with_error_recovery() {
+        let script = || {
+            mock::MockSerialPort::new()
+                .packets(2, [12_000; 13], [1_000; 13])
+                .raw(&[0x55; 7])
+                .packets(2, [12_000; 13], [1_000; 13])
+        };
+
+        let (mut p, samples) = record(
+            from_port(Box::new(script()), "mock")
+                .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(4))),
+        );
+        p.set_error_recovery(true);
+        p.start_measurement().unwrap();
+
+        let sequences: Vec<u16> = samples.lock().unwrap().iter().map(|s| s.1).collect();
+        assert_eq!(sequences, [1, 2, 3, 4]);
+        assert_eq!(p.health().resyncs, 1);
+
+        let mut p = from_port(Box::new(script()), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(4)));
+        assert!(matches!(
+            p.start_measurement(),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+    }
+
+    #[test]
+    fn timeouts_are_no_connection_loss() {
+        let io_error = |kind| PoweneticsError::Io(io::Error::new(kind, "test"));
+
+        assert!(!is_connection_loss(&io_error(io::ErrorKind::TimedOut)));
+        assert!(is_connection_loss(&io_error(io::ErrorKind::BrokenPipe)));
+        assert!(is_connection_loss(&PoweneticsError::SerialPort(
+            serialport::Error::new(serialport::ErrorKind::NoDevice, "test")
+        )));
+        assert!(!is_connection_loss(&PoweneticsError::NoSubscribers));
+    }
+
+    #[test]
+    fn unreconnectable_port_ends_the_measurement() {
+        let port = mock::MockSerialPort::new().packets(3, [12_000; 13], [1_000; 13]);
+        let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+
+        // The end of the script reads like an unplugged device, which cannot be reopened
+        assert!(matches!(
+            p.start_measurement(),
+            Err(PoweneticsError::Io(err)) if err.kind() == io::ErrorKind::UnexpectedEof
+        ));
+        assert_eq!(samples.lock().unwrap().len(), 3);
+        assert_eq!(p.health().reconnects, 0);
+    }
+
+    #[test]
+    fn excluded_channels_are_left_out_of_totals() {
+        let currents = array::from_fn(|i| 100 * (i as u32 + 1));
+        let port = mock::MockSerialPort::new().packets(5, [12_000; 13], currents);
+        let mut p = slow_down(from_port(Box::new(port), "mock"))
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(5)));
+
+        let excluded = [ChannelId::Atx5VStandby, ChannelId::PcieSlot12V];
+        p.exclude_from_totals(&excluded);
+        p.start_measurement().unwrap();
+
+        let data = p.data();
+        let included = || {
+            ChannelId::ALL
+                .into_iter()
+                .filter(|id| !excluded.contains(id))
+                .map(|id| data.channel(id))
+        };
+
+        assert!(data.is_excluded_from_totals(ChannelId::PcieSlot12V));
+        assert!(!data.is_excluded_from_totals(ChannelId::Atx12V));
+        assert_eq!(
+            data.total_power(),
+            included().map(|ch| ch.power() as u64).sum::<u64>()
+        );
+        assert_eq!(
+            data.total_energy(),
+            included().map(|ch| ch.energy()).sum::<u64>()
+        );
+        assert!(data.total_energy() < data.channels().iter().map(|ch| ch.energy()).sum());
+
+        // Replacing the exclusions includes all channels again
+        p.exclude_from_totals(&[]);
+        let data = p.data();
+        assert_eq!(
+            data.total_power(),
+            data.channels()
+                .iter()
+                .map(|ch| ch.power() as u64)
+                .sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn take_energy_returns_and_resets_the_energy() {
+        let port = mock::MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let mut p = slow_down(from_port(Box::new(port), "mock"))
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(5)));
+        p.add_virtual_channel("constant", |_| (12_000, 1_000));
+        p.start_measurement().unwrap();
+
+        let energies: [u64; POWENETICS_CHANNELS.len()] =
+            array::from_fn(|i| p.data().channels()[i].energy());
+        assert!(energies.iter().all(|&energy| energy > 0));
+        assert!(p.data().virtual_channels()[0].energy() > 0);
+
+        assert_eq!(p.data_mut().take_energy(), energies);
+        assert!(p.data().channels().iter().all(|ch| ch.energy() == 0));
+        assert_eq!(p.data().virtual_channels()[0].energy(), 0);
+        assert_eq!(p.data_mut().take_total_energy(), 0);
+    }
+
+    #[test]
+    fn secondary_energy_is_reset_independently() {
+        let port = mock::MockSerialPort::new().packets(10, [12_000; 13], [1_000; 13]);
+        let mut p = from_port(Box::new(port), "mock");
+        let mut sample = OwnedSample::default();
+        let mut read = |p: &mut Powenetics, samples| {
+            for _ in 0..samples {
+                thread::sleep(time::Duration::from_millis(1));
+                p.read_into(&mut sample).unwrap();
+            }
+        };
+
+        read(&mut p, 5);
+        let channel = p.data().channel(ChannelId::Atx12V);
+        let first = channel.energy();
+        assert!(first > 0);
+        assert_eq!(channel.energy_secondary(), first);
+
+        p.data_mut().reset_energy_secondary();
+        assert_eq!(p.data().channel(ChannelId::Atx12V).energy_secondary(), 0);
+
+        read(&mut p, 5);
+        let channel = p.data().channel(ChannelId::Atx12V);
+        assert!(channel.energy_secondary() > 0);
+        assert_eq!(channel.energy(), first + channel.energy_secondary());
+    }
+
+    #[test]
+    fn begin_session_resets_energy_and_stats_on_request() {
+        let port = mock::MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .pause()
+            .packets(3, [12_000; 13], [1_000; 13]);
+        let energy_at_start = Arc::new(Mutex::new(vec![]));
+        let mut p = slow_down(
+            from_port(Box::new(port), "mock")
+                .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(3)))
+                .with_subscriber(Box::new(Begin(energy_at_start.clone()))),
+        );
+        p.set_preserve_energy_across_restart(true);
+        p.start_measurement().unwrap();
+
+        assert!(p.data().total_energy() > 0);
+        assert_eq!(p.health().packets, 3);
+
+        // Energy only
+        p.begin_session(true, false).unwrap();
+        assert_eq!(p.data().total_energy(), 0);
+        assert!(p
+            .data()
+            .channels()
+            .iter()
+            .all(|ch| ch.energy_secondary() == 0));
+        assert_eq!(p.health().packets, 3);
+        assert!(p.port_io_stats().bytes_read > 0);
+
+        p.start_measurement().unwrap();
+        assert_eq!(*energy_at_start.lock().unwrap(), [0, 0]);
+        assert_eq!(p.health().packets, 6);
+
+        // Statistics only
+        let energy = p.data().total_energy();
+        p.begin_session(false, true).unwrap();
+        assert_eq!(p.data().total_energy(), energy);
+        assert_eq!(p.health().packets, 0);
+        assert_eq!(p.port_io_stats().bytes_read, 0);
+        assert_eq!(p.port_io_stats().bytes_written, 0);
+    }
+
+    #[test]
+    fn port_io_stats_count_the_transferred_bytes() {
+        let port = mock::MockSerialPort::new().packets(4, [12_000; 13], [1_000; 13]);
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(4)));
+        assert_eq!(p.port_io_stats().bytes_read, 0);
+
+        p.start_measurement().unwrap();
+        let stats = p.port_io_stats();
+
+        assert_eq!(
+            stats.bytes_read,
+            4 * POWENETICS_MEASUREMENT_PACKET_SIZE as u64
+        );
+        assert_eq!(stats.bytes_written, written.lock().unwrap().len() as u64);
+        assert!(stats.bytes_written >= POWENETICS_START_COMMAND.len() as u64);
+    }
+
+    #[test]
+    fn channels_are_ordered_for_display() {
+        let mut data = PoweneticsData::new();
+        data.channels[ChannelId::Pcie12V2 as usize].set_reading(12_000, 3_000);
+        data.channels[ChannelId::Atx5V as usize].set_reading(5_000, 2_000);
+        data.channels[ChannelId::Eps12V1 as usize].set_reading(12_000, 1_000);
+        let ids = |order| -> Vec<ChannelId> {
+            data.channels_ordered(order)
+                .iter()
+                .map(|ch| ch.channel_id())
+                .collect()
+        };
+
+        assert_eq!(ids(ChannelOrder::Protocol), ChannelId::ALL);
+        assert_eq!(ids(ChannelOrder::Group), CHANNEL_DISPLAY_ORDER);
+
+        let by_voltage = ids(ChannelOrder::NominalVoltage);
+        assert_eq!(
+            by_voltage[..4],
+            [
+                ChannelId::Atx3V3,
+                ChannelId::PcieSlot3V3,
+                ChannelId::Atx5VStandby,
+                ChannelId::Atx5V
+            ]
+        );
+        assert_eq!(by_voltage[4], ChannelId::Atx12V);
+
+        // 36 W, 12 W and 10 W, then the unpowered channels in protocol order
+        let by_power = ids(ChannelOrder::Power);
+        assert_eq!(
+            by_power[..5],
+            [
+                ChannelId::Pcie12V2,
+                ChannelId::Eps12V1,
+                ChannelId::Atx5V,
+                ChannelId::Atx3V3,
+                ChannelId::Atx5VStandby
+            ]
+        );
+    }
+
+    #[test]
+    fn sample_period_is_estimated_from_the_given_packets() {
+        let port = mock::MockSerialPort::new()
+            .packets(3, [12_000; 13], [1_000; 13])
+            .pause()
+            .packets(3, [12_000; 13], [1_000; 13]);
+        let (p, samples) = record(from_port(Box::new(port), "mock"));
+        let mut p = p.with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(3)));
+
+        let period = p.estimate_sample_period(3).unwrap();
+        // The mock delivers packets right away
+        assert!(period.mean < POWENETICS_TIMEOUT);
+        assert!(period.variance >= 0.0);
+        assert_eq!(
+            p.port_io_stats().bytes_read,
+            3 * POWENETICS_MEASUREMENT_PACKET_SIZE as u64
+        );
+
+        // A measurement afterwards is unaffected
+        p.start_measurement().unwrap();
+        let samples = samples.lock().unwrap();
+        assert_eq!(samples.iter().map(|s| s.1).collect::<Vec<_>>(), [4, 5, 6]);
+    }
+
+    #[test]
+    fn sample_period_needs_well_formed_packets() {
+        let port = mock::MockSerialPort::new()
+            .packet([12_000; 13], [1_000; 13])
+            .raw(&[0x55; POWENETICS_MEASUREMENT_PACKET_SIZE]);
+        let mut p = from_port(Box::new(port), "mock");
+
+        // At least two packets are read
+        assert!(matches!(
+            p.estimate_sample_period(0),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+    }
+
+    #[test]
+    fn commands_return_the_available_response() {
+        let port = mock::MockSerialPort::new()
+            .reply(&[0xCA, 0xAC, 0x01], &[0xAC, 0xCA, 0x01, 0x00])
+            .reply(&[0xCA, 0xAC, 0x02], &[0xEE]);
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock");
+
+        assert_eq!(
+            p.send_command(&[0xCA, 0xAC, 0x01], 4).unwrap(),
+            [0xAC, 0xCA, 0x01, 0x00]
+        );
+        // Shorter than expected once the timeout has passed
+        assert_eq!(p.send_command(&[0xCA, 0xAC, 0x02], 2).unwrap(), [0xEE]);
+        // Not answered at all
+        assert!(p.send_command(&[0xCA, 0xAC, 0x03], 0).unwrap().is_empty());
+
+        assert_eq!(
+            *written.lock().unwrap(),
+            [0xCA, 0xAC, 0x01, 0xCA, 0xAC, 0x02, 0xCA, 0xAC, 0x03]
+        );
+    }
+
+    #[test]
+    fn calibrate_by_id_sends_the_channel_id() {
+        let port = mock::MockSerialPort::new().reply(&[0xCA, 12, 0x00, 0x05, 0xDC], &[0xCA, 0xAC]);
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock");
+
+        p.calibrate_by_id(3, 0x01_2345).unwrap();
+        assert_eq!(*written.lock().unwrap(), [0xCA, 3, 0x01, 0x23, 0x45]);
+        assert_eq!(p.calibration_status()[3], Some(0x01_2345));
+
+        assert!(matches!(
+            p.calibrate_by_id(12, 1_500),
+            Err(PoweneticsError::NoPowerOnChannel)
+        ));
+        assert_eq!(p.calibration_status()[12], None);
+
+        assert!(matches!(
+            p.calibrate_by_id(POWENETICS_CHANNELS.len() as u8, 1_500),
+            Err(PoweneticsError::InvalidChannel)
+        ));
+        assert_eq!(written.lock().unwrap().len(), 10);
+    }
+
+    #[test]
+    fn into_port_hands_back_the_streaming_port() {
+        let port = mock::MockSerialPort::new().packets(4, [12_000; 13], [1_000; 13]);
+        let written = port.written();
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(2)));
+        p.start_measurement().unwrap();
+
+        let mut port = p.into_port();
+        assert_eq!(
+            port.bytes_to_read().unwrap() as usize,
+            2 * POWENETICS_MEASUREMENT_PACKET_SIZE
+        );
+
+        let mut buf = [0; POWENETICS_MEASUREMENT_PACKET_SIZE];
+        port.read_exact(&mut buf).unwrap();
+        assert_eq!(parse_measurement_packet(&buf).unwrap().sequence, 3);
+
+        port.write_all(&[0xCA, 0xAC, 0xBD, 0x01]).unwrap();
+        assert!(written.lock().unwrap().ends_with(&[0xCA, 0xAC, 0xBD, 0x01]));
+    }
+
+    #[test]
+    fn read_into_overwrites_the_sample_and_continues_after_gaps() {
+        let port = mock::MockSerialPort::new()
+            .packet([12_000; 13], [1_000; 13])
+            .packet([11_000; 13], [2_000; 13])
+            .skip_sequence(3)
+            .packets(2, [12_000; 13], [500; 13]);
+        let (mut p, samples) = record(from_port(Box::new(port), "mock"));
+        let mut sample = OwnedSample::default();
+
+        p.read_into(&mut sample).unwrap();
+        assert_eq!(sample.sample_index(), 0);
+        assert_eq!(*sample.voltages(), [12_000; 13]);
+        assert_eq!(*sample.currents(), [1_000; 13]);
+
+        p.read_into(&mut sample).unwrap();
+        assert_eq!(sample.sample_index(), 1);
+        assert_eq!(*sample.voltages(), [11_000; 13]);
+        assert_eq!(*sample.currents(), [2_000; 13]);
+        assert_eq!(sample.last_update(), p.data().last_update());
+
+        assert!(matches!(
+            p.read_into(&mut sample),
+            Err(PoweneticsError::Protocol { .. })
+        ));
+
+        p.read_into(&mut sample).unwrap();
+        assert_eq!(*sample.currents(), [500; 13]);
+        assert_eq!(p.data().sequence(), 7);
+        // Subscribers are not involved
+        assert!(samples.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn subscribers_are_called_by_priority() {
+        let port = mock::MockSerialPort::new().packets(2, [12_000; 13], [1_000; 13]);
+        let mut p = from_port(Box::new(port), "mock");
+        let calls = Arc::new(Mutex::new(vec![]));
+
+        for (name, priority) in [("a", 0), ("b", 10), ("c", 0), ("d", -5)] {
+            let calls = calls.clone();
+            p.subscribe_with_priority(
+                Box::new(FnSubscriber(move |_: &PoweneticsData| {
+                    calls.lock().unwrap().push(name);
+                    // The stop only takes effect once all subscribers have seen the sample
+                    Ok(if name == "b" {
+                        ControlFlow::Stop
+                    } else {
+                        ControlFlow::Continue
+                    })
+                })),
+                priority,
+            );
+        }
+        p.start_measurement().unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), ["b", "a", "c", "d"]);
+    }
+
+    /// Sample index of an update, or of the end with its stop reason
+    type Event = (u64, Option<StopReason>);
+
+    /// Record every update and the end of the measurement
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<Event>>>);
+
+    impl PoweneticsSubscriber for Events {
+        fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+            self.0.lock().unwrap().push((p.sample_index(), None));
+            Ok(ControlFlow::Continue)
+        }
+
+        fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+            self.0
+                .lock()
+                .unwrap()
+                .push((p.sample_index(), Some(reason)));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn end_follows_the_final_update_of_a_clean_stop() {
+        let port = mock::MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let events = Events::default();
+        let mut p = from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(events.clone()))
+            .with_subscriber(Box::new(limit::SampleCountLimitSubscriber::new(2)));
+        p.start_measurement().unwrap();
+
+        assert_eq!(
+            *events.0.lock().unwrap(),
+            [(0, None), (1, None), (1, Some(StopReason::Subscriber))]
+        );
+
+        // Not called when the measurement fails
+        let port = mock::MockSerialPort::new().packets(2, [12_000; 13], [1_000; 13]);
+        let events = Events::default();
+        let mut p = from_port(Box::new(port), "mock").with_subscriber(Box::new(events.clone()));
+        assert!(p.start_measurement().is_err());
+
+        assert_eq!(*events.0.lock().unwrap(), [(0, None), (1, None)]);
+    }
+
+    #[test]
+    fn busy_ports_are_reported_as_such() {
+        let error =
+            |description| serialport::Error::new(serialport::ErrorKind::Unknown, description);
+
+        assert!(matches!(
+            map_open_error(error("Device or resource busy")),
+            PoweneticsError::PortBusy(_)
+        ));
+        assert!(matches!(
+            map_open_error(error("No such file or directory")),
+            PoweneticsError::SerialPort(_)
+        ));
+    }
+
+    /// Pseudo-terminal standing in for the device's serial port, closed when dropped
+    #[cfg(target_os = "linux")]
+    struct Pty {
+        master: libc::c_int,
+        path: String,
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Pty {
+        fn open() -> Pty {
+            unsafe {
+                let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+                assert!(master >= 0, "{}", io::Error::last_os_error());
+                assert_eq!(libc::grantpt(master), 0);
+                assert_eq!(libc::unlockpt(master), 0);
+
+                let mut path = [0 as libc::c_char; 64];
+                assert_eq!(libc::ptsname_r(master, path.as_mut_ptr(), path.len()), 0);
+                let path = std::ffi::CStr::from_ptr(path.as_ptr());
+
+                Pty {
+                    master,
+                    path: path.to_string_lossy().into_owned(),
+                }
+            }
+        }
+
+        /// Whether the terminal is in exclusive mode (`TIOCEXCL`)
+        fn exclusive(&self) -> bool {
+            let path = std::ffi::CString::new(self.path.as_str()).unwrap();
+            let mut exclusive: libc::c_int = 0;
+
+            unsafe {
+                // Root may open the terminal regardless of exclusive mode
+                let fd = libc::open(
+                    path.as_ptr(),
+                    libc::O_RDWR | libc::O_NOCTTY | libc::O_NONBLOCK,
+                );
+                if fd < 0 {
+                    return io::Error::last_os_error().raw_os_error() == Some(libc::EBUSY);
+                }
+
+                assert_eq!(libc::ioctl(fd, libc::TIOCGEXCL, &mut exclusive), 0);
+                libc::close(fd);
+            }
+
+            exclusive != 0
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    impl Drop for Pty {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.master) };
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn ports_are_opened_exclusively_by_default() {
+        let pty = Pty::open();
+        let p = PoweneticsBuilder::new().open(&pty.path).unwrap();
+        assert!(pty.exclusive());
+
+        if unsafe { libc::geteuid() } != 0 {
+            assert!(matches!(
+                PoweneticsBuilder::new().open(&pty.path),
+                Err(PoweneticsError::PortBusy(_))
+            ));
+        }
+        drop(p);
+
+        let _p = PoweneticsBuilder::new()
+            .exclusive(false)
+            .open(&pty.path)
+            .unwrap();
+        assert!(!pty.exclusive());
+    }
+
+    #[test]
+    fn input_buffer_size_is_kept_for_opening() {
+        let builder = PoweneticsBuilder::new();
+        assert_eq!(builder.input_buffer_size, None);
+        assert_eq!(
+            builder.input_buffer_size(1 << 20).input_buffer_size,
+            Some(1 << 20)
+        );
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn input_buffer_size_is_ignored_off_windows() {
+        let pty = Pty::open();
+        let p = PoweneticsBuilder::new()
+            .input_buffer_size(1 << 20)
+            .open(&pty.path)
+            .unwrap();
+
+        assert!(p.port_settings.is_some());
+    }
+
+    #[test]
+    fn power_is_cached_per_reading() {
+        let mut channel = PoweneticsData::new().channels[0].clone();
+        let start = time::UNIX_EPOCH + time::Duration::from_secs(1);
+
+        channel.set_reading(12_000, 1_000);
+        channel.update_energy(start, true, IntegrationMethod::Trapezoidal);
+        assert_eq!(channel.power(), 12_000_000);
+
+        channel.set_reading(12_000, 2_000);
+        assert_eq!(channel.power(), 24_000_000);
+        assert_eq!(channel.previous_power, 12_000_000);
+
+        // Mean of the cached powers over one second
+        let energy = channel.update_energy(
+            start + time::Duration::from_secs(1),
+            true,
+            IntegrationMethod::Trapezoidal,
+        );
+        assert_eq!(energy, 18_000_000_000);
+        assert_eq!(channel.energy(), energy);
     }
 }
@@ -0,0 +1,74 @@
+use std::time;
+
+use crate::metadata::RunMetadata;
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber};
+
+/// Stops the measurement once the given duration has elapsed since the first sample, e.g. to
+/// measure a benchmark for a fixed time. The sample reaching the duration is the last one
+/// processed.
+pub struct DurationLimitSubscriber {
+    duration: time::Duration,
+    start: Option<time::SystemTime>,
+}
+
+impl DurationLimitSubscriber {
+    pub fn new(duration: time::Duration) -> Self {
+        DurationLimitSubscriber {
+            duration,
+            start: None,
+        }
+    }
+
+    /// Time elapsed between the first and the most recent sample
+    pub fn elapsed(&self, p: &PoweneticsData) -> time::Duration {
+        self.start
+            .and_then(|start| p.last_update().duration_since(start).ok())
+            .unwrap_or_default()
+    }
+}
+
+impl PoweneticsSubscriber for DurationLimitSubscriber {
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.start = None;
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        // Seeded by the first sample, timestamps are not valid before
+        self.start.get_or_insert(p.last_update());
+
+        Ok((self.elapsed(p) >= self.duration).into())
+    }
+}
+
+/// Stops the measurement after the given number of samples. Stopping takes effect once all
+/// subscribers have processed the current sample, so every subscriber sees exactly `samples`
+/// samples, including the last one.
+pub struct SampleCountLimitSubscriber {
+    samples: u64,
+    seen: u64,
+}
+
+impl SampleCountLimitSubscriber {
+    pub fn new(samples: u64) -> Self {
+        SampleCountLimitSubscriber { samples, seen: 0 }
+    }
+
+    /// Number of samples seen in the current measurement
+    pub fn seen(&self) -> u64 {
+        self.seen
+    }
+}
+
+impl PoweneticsSubscriber for SampleCountLimitSubscriber {
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.seen = 0;
+        Ok(())
+    }
+
+    fn update(&mut self, _p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        self.seen += 1;
+
+        Ok((self.seen >= self.samples).into())
+    }
+}
@@ -1,19 +1,121 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use serialport::SerialPortType;
 
-use powenetics_v2::{POWENETICS_USB_PID, POWENETICS_USB_VID};
+use powenetics_v2::{ChannelId, PoweneticsSubscriber, POWENETICS_USB_PID, POWENETICS_USB_VID};
+
+use crate::csv::{CsvOptions, TimestampFormat};
 
 mod csv;
+mod interrupt;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Comma-separated values
+    Csv,
+    /// Newline-delimited JSON, one object per sample
+    Json,
+    /// InfluxDB line protocol, one line per channel and sample
+    Influx,
+}
+
+/// Output file, optionally prefixed with its format as in `csv:run.csv`
+#[derive(Clone)]
+struct OutputSpec {
+    format: Option<Format>,
+    path: PathBuf,
+}
+
+fn parse_output_spec(spec: &str) -> Result<OutputSpec, String> {
+    if let Some((prefix, path)) = spec.split_once(':') {
+        // Anything not naming a format (e.g. a Windows drive letter) is part of the path
+        if let Ok(format) = Format::from_str(prefix, true) {
+            if path.is_empty() {
+                return Err(format!("missing path for {} output", prefix));
+            }
+
+            return Ok(OutputSpec {
+                format: Some(format),
+                path: PathBuf::from(path),
+            });
+        }
+    }
+
+    Ok(OutputSpec {
+        format: None,
+        path: PathBuf::from(spec),
+    })
+}
+
+/// Largest reference current in mA that fits into the 24 bits of the calibration command
+const MAX_CALIBRATION_REFERENCE: u32 = 0xFF_FFFF;
+
+fn parse_reference(amps: &str) -> Result<u32, String> {
+    let amps: f64 = amps
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", amps))?;
+    let milliamps = (amps * 1000.0).round();
+
+    if !(1.0..=MAX_CALIBRATION_REFERENCE as f64).contains(&milliamps) {
+        return Err(format!(
+            "reference must be between 0.001 A and {:.3} A",
+            MAX_CALIBRATION_REFERENCE as f64 / 1000.0
+        ));
+    }
+
+    Ok(milliamps as u32)
+}
+
+fn parse_channel(channel: &str) -> Result<ChannelId, String> {
+    channel
+        .parse()
+        .ok()
+        .and_then(ChannelId::from_u8)
+        .or_else(|| ChannelId::from_name(channel))
+        .ok_or_else(|| format!("unknown channel '{}'", channel))
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Calibrate a channel against a reference current
+    Calibrate {
+        /// Channel name (e.g. "ATX 12V") or ID
+        #[arg(long, value_parser = parse_channel)]
+        channel: ChannelId,
+        /// Reference current in A as measured by a reference meter
+        #[arg(long, value_name = "amps", value_parser = parse_reference)]
+        reference: u32,
+        /// Serial port name or path
+        port: String,
+    },
+}
 
 /// Powenetics v2 command line tool
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
-    /// Write measurement data to CSV file
-    #[arg(long, value_name = "path")]
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Write measurement data to file, may be given multiple times
+    #[arg(long, value_name = "[fmt:]path", value_parser = parse_output_spec)]
+    output: Vec<OutputSpec>,
+    /// Output format for --output paths without format prefix
+    #[arg(long, value_name = "fmt", value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// Write measurement data to CSV file (deprecated, use --output)
+    #[arg(long, value_name = "path", conflicts_with = "output")]
     csv: Option<PathBuf>,
+    /// Timestamp format used in the CSV file
+    #[arg(long, value_name = "format", value_enum, default_value_t = TimestampFormat::Absolute)]
+    timestamps: TimestampFormat,
+    /// Write run metadata (run ID, start time, device, calibration) as file header
+    #[arg(long)]
+    metadata: bool,
+    /// Leave out the per-channel and total power columns of the CSV file
+    #[arg(long)]
+    no_power: bool,
     /// Serial port name or path (run without arguments for list of available ports)
     port: Option<String>,
 }
@@ -21,6 +123,26 @@ struct Cli {
 fn main() -> Result<()> {
     let args = Cli::parse();
 
+    if let Some(Command::Calibrate {
+        channel,
+        reference,
+        port,
+    }) = &args.command
+    {
+        let mut p = powenetics_v2::new(port)?;
+
+        println!(
+            "Calibrating {} with reference {} mA",
+            channel.name(),
+            reference
+        );
+
+        p.calibrate_channel(*channel, *reference)?;
+        p.finalize_calibration()?;
+
+        return Ok(());
+    }
+
     if args.port.is_none() {
         println!("Usage: see --help");
 
@@ -54,13 +176,162 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
-    let mut p = powenetics_v2::new(&*args.port.unwrap())?;
+    let mut p = powenetics_v2::new(args.port.as_ref().unwrap())?;
+
+    let mut outputs = args.output.clone();
 
-    if args.csv.is_some() {
-        csv::subscribe_csv(&mut p, &args.csv.unwrap())?;
+    if let Some(path) = &args.csv {
+        outputs.push(OutputSpec {
+            format: Some(Format::Csv),
+            path: path.clone(),
+        });
     }
 
+    let mut subs = vec![];
+    let mut errors = vec![];
+
+    for output in &outputs {
+        match create_output(output.format.unwrap_or(args.format), &output.path, &args) {
+            Ok(sub) => subs.push(sub),
+            Err(err) => errors.push(format!("{}: {:#}", output.path.display(), err)),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "Unable to create outputs:\n  {}",
+            errors.join("\n  ")
+        ));
+    }
+
+    p.subscribe_many(subs);
+    interrupt::stop_on_interrupt(p.stop_handle());
+
     p.start_measurement()?;
 
     Ok(())
 }
+
+fn create_output(format: Format, path: &Path, args: &Cli) -> Result<Box<dyn PoweneticsSubscriber>> {
+    let sub = match format {
+        Format::Csv => csv::create_csv(
+            path,
+            CsvOptions {
+                timestamps: args.timestamps,
+                metadata: args.metadata,
+                power: !args.no_power,
+            },
+        )?,
+        Format::Json => powenetics_v2::json::create_json(path, args.metadata)?,
+        Format::Influx => powenetics_v2::influx::create_influx(path)?,
+    };
+
+    Ok(sub)
+}
+
+#[cfg(test)]
+mod tests {
+    use powenetics_v2::limit::SampleCountLimitSubscriber;
+    use powenetics_v2::mock::MockSerialPort;
+
+    use super::*;
+
+    #[test]
+    fn every_format_parses() {
+        for format in Format::value_variants() {
+            let name = format.to_possible_value().unwrap();
+            let cli = Cli::try_parse_from(["powenetics-v2", "--format", name.get_name(), "port"])
+                .unwrap();
+            assert_eq!(cli.format, *format);
+
+            let spec = parse_output_spec(&format!("{}:out", name.get_name())).unwrap();
+            assert_eq!(spec.format, Some(*format));
+
+            // The subscriber created for the format writes that format
+            let path = std::env::temp_dir().join(format!(
+                "powenetics-{}-format-{}",
+                std::process::id(),
+                name.get_name()
+            ));
+            let _ = std::fs::remove_file(&path);
+
+            let port = MockSerialPort::new().packet([12_000; 13], [1_000; 13]);
+            let mut p = powenetics_v2::from_port(Box::new(port), "mock")
+                .with_subscriber(create_output(*format, &path, &cli).unwrap())
+                .with_subscriber(Box::new(SampleCountLimitSubscriber::new(1)));
+            p.start_measurement().unwrap();
+
+            let out = std::fs::read_to_string(&path).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            let first = out.lines().next().unwrap();
+
+            match format {
+                Format::Csv => assert!(first.starts_with("Timestamp,")),
+                Format::Json => assert!(first.starts_with("{\"last_update\":")),
+                Format::Influx => assert!(first.starts_with("powenetics,channel=")),
+            }
+        }
+
+        assert!(Cli::try_parse_from(["powenetics-v2", "--format", "xml", "port"]).is_err());
+    }
+
+    #[test]
+    fn output_specs_are_parsed() {
+        let cli = Cli::try_parse_from([
+            "powenetics-v2",
+            "--output",
+            "csv:run.csv",
+            "--output",
+            "JSON:run.json",
+            "--output",
+            "run.lp",
+            "--output",
+            "C:\\runs\\run.csv",
+            "port",
+        ])
+        .unwrap();
+
+        let outputs: Vec<_> = cli
+            .output
+            .iter()
+            .map(|spec| (spec.format, spec.path.to_str().unwrap()))
+            .collect();
+        assert_eq!(
+            outputs,
+            [
+                (Some(Format::Csv), "run.csv"),
+                (Some(Format::Json), "run.json"),
+                (None, "run.lp"),
+                (None, "C:\\runs\\run.csv"),
+            ]
+        );
+
+        assert_eq!(
+            parse_output_spec("influx:").err(),
+            Some(String::from("missing path for influx output"))
+        );
+        assert!(Cli::try_parse_from(["powenetics-v2", "--output", "csv:", "port"]).is_err());
+    }
+
+    #[test]
+    fn references_are_parsed_to_milliamps() {
+        assert_eq!(parse_reference("1.5"), Ok(1_500));
+        assert_eq!(
+            parse_reference("0.0004"),
+            Err(String::from(
+                "reference must be between 0.001 A and 16777.215 A"
+            ))
+        );
+        assert_eq!(parse_reference("0.0005"), Ok(1));
+        assert_eq!(parse_reference("2.0006"), Ok(2_001));
+        assert_eq!(parse_reference("16777.215"), Ok(MAX_CALIBRATION_REFERENCE));
+
+        assert!(parse_reference("-1").is_err());
+        assert!(parse_reference("16777.216").is_err());
+        assert!(parse_reference("nan").is_err());
+        assert_eq!(
+            parse_reference("1.5A"),
+            Err(String::from("'1.5A' is not a number"))
+        );
+    }
+}
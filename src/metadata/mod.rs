@@ -0,0 +1,123 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time;
+
+/// Conditions under which a measurement run was recorded, captured when the measurement starts
+#[derive(Clone, Debug)]
+pub struct RunMetadata {
+    run_id: String,
+    start_time: time::SystemTime,
+    device_banner: Option<String>,
+    port_name: String,
+    crate_version: &'static str,
+    calibrated_channels: Vec<u8>,
+}
+
+impl RunMetadata {
+    pub(crate) fn new(
+        device_banner: Option<String>,
+        port_name: &str,
+        calibrated_channels: &[u8],
+    ) -> Self {
+        RunMetadata {
+            run_id: generate_run_id(),
+            start_time: time::SystemTime::now(),
+            device_banner,
+            port_name: String::from(port_name),
+            crate_version: env!("CARGO_PKG_VERSION"),
+            calibrated_channels: calibrated_channels.to_vec(),
+        }
+    }
+
+    /// Random UUID (version 4) identifying this run
+    pub fn run_id(&self) -> &str {
+        &self.run_id
+    }
+
+    pub fn start_time(&self) -> time::SystemTime {
+        self.start_time
+    }
+
+    /// Ready message sent by the device, if it was freshly plugged in
+    pub fn device_banner(&self) -> Option<&str> {
+        self.device_banner.as_deref()
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn crate_version(&self) -> &str {
+        self.crate_version
+    }
+
+    /// IDs of the channels calibrated on this device since it was opened
+    pub fn calibrated_channels(&self) -> &[u8] {
+        &self.calibrated_channels
+    }
+
+    /// Metadata as key/value pairs, e.g. for writing file headers
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let calibrated = if self.calibrated_channels.is_empty() {
+            String::from("none")
+        } else {
+            self.calibrated_channels
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        vec![
+            ("run_id", self.run_id.clone()),
+            (
+                "start_time",
+                format!(
+                    "{:.5}",
+                    self.start_time
+                        .duration_since(time::SystemTime::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64()
+                ),
+            ),
+            (
+                "device_banner",
+                self.device_banner.clone().unwrap_or_default(),
+            ),
+            ("port_name", self.port_name.clone()),
+            ("crate_version", String::from(self.crate_version)),
+            ("calibrated_channels", calibrated),
+        ]
+    }
+}
+
+fn generate_run_id() -> String {
+    // RandomState is seeded randomly by the standard library, which is good enough for telling
+    // runs apart without pulling in a dedicated RNG
+    let mut bytes = [0u8; 16];
+
+    for (i, half) in bytes.chunks_mut(8).enumerate() {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_usize(i);
+        hasher.write_u128(
+            time::SystemTime::now()
+                .duration_since(time::SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos(),
+        );
+        half.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+
+    bytes[6] = (bytes[6] & 0x0f) | 0x40;
+    bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
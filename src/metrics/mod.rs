@@ -0,0 +1,155 @@
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber};
+
+/// Destination for the values recorded by `MetricsSubscriber`, labeled by channel name where
+/// `channel` is given. `FacadeRecorder` records to the `metrics` facade, other implementations
+/// e.g. collect the values in tests.
+pub trait MetricsRecorder: Send {
+    fn gauge(&mut self, name: &'static str, channel: Option<&str>, value: f64);
+    /// Set a counter to the absolute `value`
+    fn counter(&mut self, name: &'static str, channel: Option<&str>, value: u64);
+}
+
+/// Records to the global recorder of the `metrics` facade, with channels as the `channel` label
+#[derive(Copy, Clone, Debug, Default)]
+pub struct FacadeRecorder;
+
+impl MetricsRecorder for FacadeRecorder {
+    fn gauge(&mut self, name: &'static str, channel: Option<&str>, value: f64) {
+        match channel {
+            Some(channel) => ::metrics::gauge!(name, "channel" => channel.to_owned()).set(value),
+            None => ::metrics::gauge!(name).set(value),
+        }
+    }
+
+    fn counter(&mut self, name: &'static str, channel: Option<&str>, value: u64) {
+        match channel {
+            Some(channel) => {
+                ::metrics::counter!(name, "channel" => channel.to_owned()).absolute(value)
+            }
+            None => ::metrics::counter!(name).absolute(value),
+        }
+    }
+}
+
+/// Records every sample as metrics, e.g. `MetricsSubscriber::new(FacadeRecorder)` for apps
+/// already using the `metrics` facade.
+///
+/// Per channel, the gauges `powenetics.voltage` (V), `powenetics.current` (A) and
+/// `powenetics.power` (W), and the counter `powenetics.energy` (nJ) are recorded, so the label
+/// cardinality is bounded by the 13 channels. The counter `powenetics.dropped_packets` holds the
+/// packets dropped in the current measurement (see `PoweneticsData::dropped_samples`) and is not
+/// labeled, as drops affect all channels alike. Counters are set to their absolute values.
+pub struct MetricsSubscriber<R: MetricsRecorder> {
+    recorder: R,
+}
+
+impl<R: MetricsRecorder> MetricsSubscriber<R> {
+    pub fn new(recorder: R) -> Self {
+        MetricsSubscriber { recorder }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.recorder
+    }
+}
+
+impl<R: MetricsRecorder> PoweneticsSubscriber for MetricsSubscriber<R> {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        for channel in p.channels() {
+            let name = Some(channel.name());
+
+            self.recorder
+                .gauge("powenetics.voltage", name, channel.voltage_volts());
+            self.recorder
+                .gauge("powenetics.current", name, channel.current_amps());
+            self.recorder
+                .gauge("powenetics.power", name, channel.power_watts());
+            self.recorder
+                .counter("powenetics.energy", name, channel.energy());
+        }
+
+        self.recorder
+            .counter("powenetics.dropped_packets", None, p.dropped_samples());
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+    use crate::POWENETICS_CHANNELS;
+
+    #[derive(Debug, PartialEq)]
+    enum Value {
+        Gauge(f64),
+        Counter(u64),
+    }
+
+    #[derive(Default)]
+    struct Recording(Vec<(&'static str, Option<String>, Value)>);
+
+    impl MetricsRecorder for Recording {
+        fn gauge(&mut self, name: &'static str, channel: Option<&str>, value: f64) {
+            self.0
+                .push((name, channel.map(String::from), Value::Gauge(value)));
+        }
+
+        fn counter(&mut self, name: &'static str, channel: Option<&str>, value: u64) {
+            self.0
+                .push((name, channel.map(String::from), Value::Counter(value)));
+        }
+    }
+
+    #[test]
+    fn every_sample_records_all_channels_and_drops() {
+        let port = MockSerialPort::new()
+            .packet([12_000; 13], [1_500; 13])
+            .skip_sequence(2)
+            .packet([12_000; 13], [1_500; 13]);
+        let metrics = Arc::new(Mutex::new(MetricsSubscriber::new(Recording::default())));
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(metrics.clone()))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(2)));
+        p.set_max_drop_rate(Some(1.0));
+        p.start_measurement().unwrap();
+
+        let metrics = metrics.lock().unwrap();
+        let recorded = &metrics.recorder.0;
+        let per_sample = 4 * POWENETICS_CHANNELS.len() + 1;
+        assert_eq!(recorded.len(), 2 * per_sample);
+
+        for (sample, dropped) in recorded.chunks(per_sample).zip([0, 2]) {
+            for (values, name) in sample.chunks(4).zip(POWENETICS_CHANNELS) {
+                let channel = Some(String::from(name));
+
+                assert_eq!(
+                    values[0],
+                    ("powenetics.voltage", channel.clone(), Value::Gauge(12.0))
+                );
+                assert_eq!(
+                    values[1],
+                    ("powenetics.current", channel.clone(), Value::Gauge(1.5))
+                );
+                assert_eq!(
+                    values[2],
+                    ("powenetics.power", channel.clone(), Value::Gauge(18.0))
+                );
+                assert!(matches!(
+                    values[3],
+                    ("powenetics.energy", ref label, Value::Counter(_)) if *label == channel
+                ));
+            }
+
+            assert_eq!(
+                sample[per_sample - 1],
+                ("powenetics.dropped_packets", None, Value::Counter(dropped))
+            );
+        }
+    }
+}
@@ -0,0 +1,390 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+use std::{io, time};
+
+use crate::emulated::{emulated_serial_port, EmulatedPort};
+use crate::layout::PacketLayout;
+use crate::{
+    ParsedPacket, POWENETICS_CHANNELS, POWENETICS_READY_MESSAGE, POWENETICS_START_COMMAND,
+};
+
+/// Emulated serial port streaming a scripted sequence of bytes, e.g. to test subscribers with
+/// `from_port` without a device.
+///
+/// The script is built from measurement packets and raw bytes, which allows exercising sequence
+/// gaps, device resets, stalls and malformed data as well. Like the device, the port only starts
+/// streaming once the start command has been written, and pauses after a device reset (or where
+/// the script says so) until it is written again. Once the script is exhausted, reads fail,
+/// which ends a measurement that has not been stopped before with an I/O error. Responses to
+/// other commands can be set up with `reply`; they are available right away, whether the port is
+/// streaming or not.
+pub struct MockSerialPort {
+    script: VecDeque<u8>,
+    // Bytes appended to and read from the script so far
+    appended: usize,
+    consumed: usize,
+    // Script offsets at which streaming pauses until the next start command
+    pauses: VecDeque<usize>,
+    // Script offsets at which one read times out
+    stalls: VecDeque<usize>,
+    sequence: u16,
+    started: bool,
+    ignored_starts: u32,
+    // Expected commands and their responses, in order
+    replies: VecDeque<(Vec<u8>, Vec<u8>)>,
+    response: VecDeque<u8>,
+    fail_bytes_to_read: bool,
+    written: Arc<Mutex<Vec<u8>>>,
+    timeout: time::Duration,
+}
+
+impl Default for MockSerialPort {
+    fn default() -> Self {
+        MockSerialPort {
+            script: VecDeque::new(),
+            appended: 0,
+            consumed: 0,
+            pauses: VecDeque::new(),
+            stalls: VecDeque::new(),
+            sequence: 1,
+            started: false,
+            ignored_starts: 0,
+            replies: VecDeque::new(),
+            response: VecDeque::new(),
+            fail_bytes_to_read: false,
+            written: Arc::new(Mutex::new(vec![])),
+            timeout: crate::POWENETICS_TIMEOUT,
+        }
+    }
+}
+
+impl MockSerialPort {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Append a measurement packet with voltages in mV and currents in mA in protocol order.
+    /// Packets are numbered consecutively unless `skip_sequence` is used.
+    pub fn packet(
+        mut self,
+        voltages: [u16; POWENETICS_CHANNELS.len()],
+        currents: [u32; POWENETICS_CHANNELS.len()],
+    ) -> Self {
+        let buf = PacketLayout::DEFAULT.encode(&ParsedPacket {
+            sequence: self.sequence,
+            voltages,
+            currents,
+        });
+
+        self = self.raw(&buf);
+        self.sequence = self.sequence.wrapping_add(1);
+        self
+    }
+
+    /// Append `count` packets with the same readings
+    pub fn packets(
+        mut self,
+        count: usize,
+        voltages: [u16; POWENETICS_CHANNELS.len()],
+        currents: [u32; POWENETICS_CHANNELS.len()],
+    ) -> Self {
+        for _ in 0..count {
+            self = self.packet(voltages, currents);
+        }
+
+        self
+    }
+
+    /// Leave out `count` sequence numbers, as if packets had been dropped
+    pub fn skip_sequence(mut self, count: u16) -> Self {
+        self.sequence = self.sequence.wrapping_add(count);
+        self
+    }
+
+    /// Append the ready message the device sends after resetting itself. Packets following it
+    /// are numbered from 1 again.
+    pub fn device_reset(mut self) -> Self {
+        self = self.raw(POWENETICS_READY_MESSAGE.as_bytes()).pause();
+        self.sequence = 1;
+        self
+    }
+
+    /// Stop streaming at this point of the script until the start command is written again, e.g.
+    /// to split the script across several measurements
+    pub fn pause(mut self) -> Self {
+        self.pauses.push_back(self.appended);
+        self
+    }
+
+    /// Let one read time out at this point of the script, as if the device paused sending for
+    /// longer than the read timeout
+    pub fn stall(mut self) -> Self {
+        self.stalls.push_back(self.appended);
+        self
+    }
+
+    /// Ignore the first `count` start commands, as if the device missed them
+    pub fn ignore_starts(mut self, count: u32) -> Self {
+        self.ignored_starts = count;
+        self
+    }
+
+    /// Respond to the next write of exactly `command` with `response`. Replies are matched in the
+    /// order they were set up, writes not matching the next one are not answered.
+    pub fn reply(mut self, command: &[u8], response: &[u8]) -> Self {
+        self.replies
+            .push_back((command.to_vec(), response.to_vec()));
+        self
+    }
+
+    /// Fail all queries of the number of bytes available, as with a broken driver
+    pub fn fail_bytes_to_read(mut self) -> Self {
+        self.fail_bytes_to_read = true;
+        self
+    }
+
+    /// Append arbitrary bytes
+    pub fn raw(mut self, bytes: &[u8]) -> Self {
+        self.script.extend(bytes);
+        self.appended += bytes.len();
+        self
+    }
+
+    /// Handle to all bytes written to the port, i.e. the commands sent, which remains valid
+    /// after the port has been handed to `from_port`
+    pub fn written(&self) -> Arc<Mutex<Vec<u8>>> {
+        self.written.clone()
+    }
+
+    fn available(&self) -> usize {
+        if !self.started {
+            return self.response.len();
+        }
+
+        let end = [self.pauses.front(), self.stalls.front()]
+            .into_iter()
+            .flatten()
+            .min()
+            .map_or(self.script.len(), |end| end - self.consumed);
+
+        self.response.len() + end
+    }
+}
+
+impl Read for MockSerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.response.is_empty() {
+            let len = buf.len().min(self.response.len());
+            for (dst, src) in buf.iter_mut().zip(self.response.drain(..len)) {
+                *dst = src;
+            }
+
+            return Ok(len);
+        }
+
+        if self.script.is_empty() {
+            // End of script
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.available());
+        if len == 0 {
+            if self.started && self.stalls.front() == Some(&self.consumed) {
+                self.stalls.pop_front();
+                return Err(io::Error::new(io::ErrorKind::TimedOut, "stalled"));
+            }
+
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "waiting for the start command",
+            ));
+        }
+
+        for (dst, src) in buf.iter_mut().zip(self.script.drain(..len)) {
+            *dst = src;
+        }
+
+        self.consumed += len;
+        Ok(len)
+    }
+}
+
+impl Write for MockSerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.written
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .extend_from_slice(buf);
+
+        if self
+            .replies
+            .front()
+            .is_some_and(|(command, _)| command == buf)
+        {
+            let (_, response) = self.replies.pop_front().unwrap();
+            self.response.extend(response);
+        }
+
+        if buf
+            .windows(POWENETICS_START_COMMAND.len())
+            .any(|w| w == POWENETICS_START_COMMAND)
+        {
+            if self.ignored_starts != 0 {
+                self.ignored_starts -= 1;
+                return Ok(buf.len());
+            }
+
+            self.started = true;
+
+            if self.pauses.front() == Some(&self.consumed) {
+                self.pauses.pop_front();
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EmulatedPort for MockSerialPort {
+    fn port_name(&self) -> &'static str {
+        "mock"
+    }
+
+    fn pending(&self) -> serialport::Result<usize> {
+        if self.fail_bytes_to_read {
+            return Err(serialport::Error::new(
+                serialport::ErrorKind::Io(io::ErrorKind::Other),
+                "bytes_to_read failed",
+            ));
+        }
+
+        Ok(self.available())
+    }
+
+    fn read_timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) {
+        self.timeout = timeout;
+    }
+}
+
+emulated_serial_port!(MockSerialPort);
+
+#[cfg(test)]
+mod tests {
+    use serialport::SerialPort;
+
+    use super::*;
+
+    fn start(port: &mut MockSerialPort) {
+        port.write_all(&POWENETICS_START_COMMAND).unwrap();
+    }
+
+    #[test]
+    fn streams_only_after_the_start_command() {
+        let mut port = MockSerialPort::new().packet([12_000; 13], [1_000; 13]);
+        let mut buf = [0; 69];
+
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+        assert_eq!(
+            port.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+
+        start(&mut port);
+        assert_eq!(port.bytes_to_read().unwrap(), 69);
+        port.read_exact(&mut buf).unwrap();
+        assert_eq!(
+            crate::layout::PacketParser::DEFAULT
+                .parse(&buf)
+                .unwrap()
+                .sequence,
+            1
+        );
+
+        // End of script
+        assert_eq!(port.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn stalls_time_out_once() {
+        let mut port = MockSerialPort::new().raw(&[1, 2]).stall().raw(&[3]);
+        let mut buf = [0; 3];
+        start(&mut port);
+
+        assert_eq!(port.bytes_to_read().unwrap(), 2);
+        assert_eq!(port.read(&mut buf).unwrap(), 2);
+        assert_eq!(
+            port.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::TimedOut
+        );
+        assert_eq!(port.bytes_to_read().unwrap(), 1);
+        assert_eq!(port.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 3);
+    }
+
+    #[test]
+    fn pauses_last_until_the_next_start_command() {
+        let mut port = MockSerialPort::new().raw(&[1]).pause().raw(&[2]);
+        let mut buf = [0; 2];
+        start(&mut port);
+
+        assert_eq!(port.read(&mut buf).unwrap(), 1);
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+
+        start(&mut port);
+        assert_eq!(port.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 2);
+    }
+
+    #[test]
+    fn ignored_start_commands_do_not_start_streaming() {
+        let mut port = MockSerialPort::new().ignore_starts(2).raw(&[1]);
+
+        start(&mut port);
+        start(&mut port);
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+
+        start(&mut port);
+        assert_eq!(port.bytes_to_read().unwrap(), 1);
+    }
+
+    #[test]
+    fn replies_precede_the_stream_in_order() {
+        let mut port = MockSerialPort::new()
+            .reply(&[1], &[10, 11])
+            .reply(&[2], &[20])
+            .raw(&[30]);
+        let written = port.written();
+        let mut buf = [0; 4];
+
+        // Out of order, not answered
+        port.write_all(&[2]).unwrap();
+        assert_eq!(port.bytes_to_read().unwrap(), 0);
+
+        port.write_all(&[1]).unwrap();
+        port.write_all(&[2]).unwrap();
+        assert_eq!(port.bytes_to_read().unwrap(), 3);
+        port.read_exact(&mut buf[..3]).unwrap();
+        assert_eq!(buf[..3], [10, 11, 20]);
+
+        start(&mut port);
+        port.read_exact(&mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 30);
+        assert_eq!(written.lock().unwrap()[..3], [2, 1, 2]);
+    }
+
+    #[test]
+    fn bytes_to_read_can_fail() {
+        let port = MockSerialPort::new().fail_bytes_to_read();
+
+        assert!(port.bytes_to_read().is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::fmt::Write as _;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::{thread, time};
+
+use crate::{ControlFlow, OwnedSample, PoweneticsData, PoweneticsSubscriber, POWENETICS_CHANNELS};
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Keeps the most recent sample for Prometheus to scrape through `PrometheusMetrics`.
+///
+/// Channels are labeled with their default names from `POWENETICS_CHANNELS` and their IDs, so
+/// series stay stable when channels are renamed. Totals leave out channels excluded with
+/// `Powenetics::exclude_from_totals`.
+#[derive(Default)]
+pub struct PrometheusSubscriber {
+    metrics: PrometheusMetrics,
+}
+
+impl PrometheusSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle to the recorded values, which remains valid after subscribing
+    pub fn metrics(&self) -> PrometheusMetrics {
+        self.metrics.clone()
+    }
+}
+
+impl PoweneticsSubscriber for PrometheusSubscriber {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let mut state = self.metrics.lock();
+
+        state.latest = Some(p.snapshot());
+        state.total_power = p.total_power();
+        state.total_energy = p.total_energy();
+        state.samples += 1;
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[derive(Default)]
+struct State {
+    latest: Option<OwnedSample>,
+    // Totals need the exclusions, which the sample does not carry
+    total_power: u64,
+    total_energy: u64,
+    samples: u64,
+}
+
+/// Values recorded by a `PrometheusSubscriber`, rendered in the Prometheus text format
+#[derive(Clone, Default)]
+pub struct PrometheusMetrics {
+    state: Arc<Mutex<State>>,
+}
+
+impl PrometheusMetrics {
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    /// Render all metrics. Before the first sample, only `powenetics_samples_total` is present.
+    pub fn render(&self) -> String {
+        let (latest, total_power, total_energy, samples) = {
+            let state = self.lock();
+            (
+                state.latest,
+                state.total_power,
+                state.total_energy,
+                state.samples,
+            )
+        };
+
+        let mut out = String::new();
+
+        metric_header(
+            &mut out,
+            "powenetics_samples_total",
+            "counter",
+            "Samples received",
+        );
+        let _ = writeln!(out, "powenetics_samples_total {}", samples);
+
+        let Some(sample) = latest else {
+            return out;
+        };
+
+        let powers = sample.powers();
+
+        channel_metric(
+            &mut out,
+            "powenetics_voltage_volts",
+            "Channel voltage",
+            sample.voltages().iter().map(|&v| v as f64 / 1e3),
+        );
+        channel_metric(
+            &mut out,
+            "powenetics_current_amperes",
+            "Channel current",
+            sample.currents().iter().map(|&c| c as f64 / 1e3),
+        );
+        channel_metric(
+            &mut out,
+            "powenetics_power_watts",
+            "Channel power",
+            powers.iter().map(|&p| p as f64 / 1e6),
+        );
+        channel_metric(
+            &mut out,
+            "powenetics_energy_joules",
+            "Channel energy since the start of the measurement or the last energy reset",
+            sample.energies().iter().map(|&e| e as f64 / 1e9),
+        );
+
+        metric_header(
+            &mut out,
+            "powenetics_total_power_watts",
+            "gauge",
+            "Total power",
+        );
+        let _ = writeln!(
+            out,
+            "powenetics_total_power_watts {}",
+            total_power as f64 / 1e6
+        );
+
+        metric_header(
+            &mut out,
+            "powenetics_total_energy_joules",
+            "gauge",
+            "Total energy",
+        );
+        let _ = writeln!(
+            out,
+            "powenetics_total_energy_joules {}",
+            total_energy as f64 / 1e9
+        );
+
+        let timestamp = sample
+            .last_update()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs_f64();
+
+        metric_header(
+            &mut out,
+            "powenetics_last_update_seconds",
+            "gauge",
+            "Reception time of the latest sample in seconds since the Unix epoch",
+        );
+        let _ = writeln!(out, "powenetics_last_update_seconds {}", timestamp);
+
+        out
+    }
+
+    /// Serve the metrics at `http://<addr>/metrics` on a background thread, returning the bound
+    /// address (useful with port 0). Scrapes are answered one at a time.
+    pub fn serve<A: ToSocketAddrs>(self, addr: A) -> io::Result<std::net::SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+
+        thread::Builder::new()
+            .name(String::from("powenetics-metrics"))
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|stream| self.respond(stream));
+
+                    if let Err(err) = result {
+                        log::debug!("metrics request failed: {}", err);
+                    }
+                }
+            })?;
+
+        Ok(local)
+    }
+
+    fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(time::Duration::from_secs(5)))?;
+
+        // Only the request line matters, which fits into the first read for any real client
+        let mut buf = [0; 1024];
+        let len = stream.read(&mut buf)?;
+        let request = String::from_utf8_lossy(&buf[..len]);
+        let mut parts = request.split_whitespace();
+
+        let (status, body) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => ("200 OK", self.render()),
+            (Some("GET"), _) => ("404 Not Found", String::from("Not found\n")),
+            _ => (
+                "405 Method Not Allowed",
+                String::from("Method not allowed\n"),
+            ),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            CONTENT_TYPE,
+            body.len(),
+            body
+        )?;
+        stream.flush()
+    }
+}
+
+fn metric_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn channel_metric(out: &mut String, name: &str, help: &str, values: impl Iterator<Item = f64>) {
+    metric_header(out, name, "gauge", help);
+
+    for (id, (channel, value)) in POWENETICS_CHANNELS.iter().zip(values).enumerate() {
+        let _ = writeln!(
+            out,
+            "{}{{channel=\"{}\",id=\"{}\"}} {}",
+            name, channel, id, value
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+    use crate::ChannelId;
+
+    #[test]
+    fn totals_leave_out_excluded_channels() {
+        let port = MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let prometheus = PrometheusSubscriber::new();
+        let metrics = prometheus.metrics();
+
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(prometheus))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(3)));
+        p.exclude_from_totals(&ChannelId::ALL[1..]);
+        p.start_measurement().unwrap();
+
+        let rendered = metrics.render();
+
+        assert!(rendered.contains("powenetics_samples_total 3\n"));
+        assert!(rendered.contains("powenetics_total_power_watts 12\n"));
+        assert!(rendered.contains(&format!(
+            "powenetics_total_energy_joules {}\n",
+            p.data().channel(ChannelId::ALL[0]).energy() as f64 / 1e9
+        )));
+    }
+}
@@ -0,0 +1,256 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::{io, thread, time};
+
+use crate::emulated::{emulated_serial_port, EmulatedPort};
+use crate::layout::PacketLayout;
+use crate::{
+    OwnedSample, ParsedPacket, POWENETICS_MEASUREMENT_PACKET_SIZE, POWENETICS_START_COMMAND,
+};
+
+const DEFAULT_MAX_DELAY: time::Duration = time::Duration::from_secs(1);
+
+/// Emulated serial port replaying recorded samples as measurement packets.
+///
+/// Commands written to the port are accepted silently; packets are produced once the start
+/// command has been received. By default, packets are available as fast as they are read. With
+/// `pace(true)`, each packet only becomes available after the interval between its sample and the
+/// previous one (as recorded in the samples' timestamps) has elapsed, reproducing the original
+/// cadence including its jitter. Intervals are capped at `max_delay` (1 s by default) so gaps in
+/// the recording do not stall the replay. Timestamps going backwards are treated as no delay.
+///
+/// Note that replayed samples are timestamped upon reception like samples read from a real device,
+/// they do not retain their recorded timestamps.
+pub struct ReplaySerialPort {
+    samples: Vec<OwnedSample>,
+    next: usize,
+    pending: VecDeque<u8>,
+    sequence: u16,
+    pace: bool,
+    max_delay: time::Duration,
+    // Offset from the start of the replay at which the next sample is due
+    due: time::Duration,
+    started: Option<time::Instant>,
+    timeout: time::Duration,
+}
+
+impl ReplaySerialPort {
+    pub fn new(samples: Vec<OwnedSample>) -> Self {
+        ReplaySerialPort {
+            samples,
+            next: 0,
+            pending: VecDeque::new(),
+            sequence: 1,
+            pace: false,
+            max_delay: DEFAULT_MAX_DELAY,
+            due: time::Duration::ZERO,
+            started: None,
+            timeout: crate::POWENETICS_TIMEOUT,
+        }
+    }
+
+    /// Reproduce the recorded inter-packet timing instead of replaying as fast as possible
+    pub fn pace(mut self, pace: bool) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// Maximum delay between two paced packets
+    pub fn max_delay(mut self, max_delay: time::Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    fn interval(&self, index: usize) -> time::Duration {
+        if index == 0 {
+            return time::Duration::ZERO;
+        }
+
+        self.samples[index]
+            .last_update()
+            .duration_since(self.samples[index - 1].last_update())
+            .unwrap_or_default()
+            .min(self.max_delay)
+    }
+
+    /// Number of samples not yet encoded that are due at this point
+    fn due_samples(&self) -> usize {
+        let Some(started) = self.started else {
+            return 0;
+        };
+
+        if !self.pace {
+            return self.samples.len() - self.next;
+        }
+
+        let elapsed = started.elapsed();
+        let mut due = self.due;
+        let mut count = 0;
+
+        while self.next + count < self.samples.len() && due <= elapsed {
+            count += 1;
+
+            if self.next + count < self.samples.len() {
+                due += self.interval(self.next + count);
+            }
+        }
+
+        count
+    }
+
+    fn encode_next(&mut self) {
+        let sample = &self.samples[self.next];
+        let packet = PacketLayout::DEFAULT.encode(&ParsedPacket {
+            sequence: self.sequence,
+            voltages: *sample.voltages(),
+            currents: *sample.currents(),
+        });
+
+        self.pending.extend(packet);
+        self.sequence = self.sequence.wrapping_add(1);
+        self.next += 1;
+
+        if self.next < self.samples.len() {
+            self.due += self.interval(self.next);
+        }
+    }
+}
+
+impl Read for ReplaySerialPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.is_empty() {
+            let Some(started) = self.started else {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "replay not started",
+                ));
+            };
+
+            if self.next >= self.samples.len() {
+                // End of recording
+                return Ok(0);
+            }
+
+            if self.pace {
+                if let Some(wait) = self.due.checked_sub(started.elapsed()) {
+                    thread::sleep(wait);
+                }
+            }
+
+            self.encode_next();
+        }
+
+        let len = buf.len().min(self.pending.len());
+        for (dst, src) in buf.iter_mut().zip(self.pending.drain(..len)) {
+            *dst = src;
+        }
+
+        Ok(len)
+    }
+}
+
+impl Write for ReplaySerialPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.started.is_none()
+            && buf
+                .windows(POWENETICS_START_COMMAND.len())
+                .any(|w| w == POWENETICS_START_COMMAND)
+        {
+            self.started = Some(time::Instant::now());
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl EmulatedPort for ReplaySerialPort {
+    fn port_name(&self) -> &'static str {
+        "replay"
+    }
+
+    fn pending(&self) -> serialport::Result<usize> {
+        Ok(self.pending.len() + self.due_samples() * POWENETICS_MEASUREMENT_PACKET_SIZE)
+    }
+
+    fn read_timeout(&self) -> time::Duration {
+        self.timeout
+    }
+
+    fn set_read_timeout(&mut self, timeout: time::Duration) {
+        self.timeout = timeout;
+    }
+}
+
+emulated_serial_port!(ReplaySerialPort);
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::ControlFlow;
+
+    /// Replay samples recorded at the given offsets in ms and return when they arrived
+    fn replay(offsets: &[u64], pace: bool) -> Vec<time::Duration> {
+        let start = time::SystemTime::now();
+        let samples = offsets
+            .iter()
+            .map(|&offset| OwnedSample {
+                last_update: start + time::Duration::from_millis(offset),
+                ..OwnedSample::default()
+            })
+            .collect();
+        let port = ReplaySerialPort::new(samples)
+            .pace(pace)
+            .max_delay(time::Duration::from_millis(30));
+
+        let arrivals = Arc::new(Mutex::new(vec![]));
+        let recorded = arrivals.clone();
+        let mut p = crate::from_port(Box::new(port), "replay")
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(
+                offsets.len() as u64
+            )))
+            .with_subscriber_fn(move |p| {
+                recorded.lock().unwrap().push(p.elapsed());
+                Ok(ControlFlow::Continue)
+            });
+        p.start_measurement().unwrap();
+
+        let arrivals = arrivals.lock().unwrap().clone();
+        arrivals
+    }
+
+    #[test]
+    fn paced_replay_follows_the_recorded_cadence() {
+        // The gap of 10 s is capped at 30 ms
+        let arrivals = replay(&[0, 20, 40, 60, 80, 10_080], true);
+        let intervals: Vec<time::Duration> = arrivals.windows(2).map(|w| w[1] - w[0]).collect();
+
+        for interval in &intervals[..4] {
+            assert!(
+                *interval >= time::Duration::from_millis(15),
+                "{:?}",
+                interval
+            );
+            assert!(
+                *interval < time::Duration::from_millis(100),
+                "{:?}",
+                interval
+            );
+        }
+        assert!(intervals[4] >= time::Duration::from_millis(25));
+        assert!(intervals[4] < time::Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unpaced_replay_runs_as_fast_as_possible() {
+        let arrivals = replay(&[0, 20, 40, 60, 80, 10_080], false);
+
+        assert!(arrivals[5] < time::Duration::from_millis(80));
+    }
+}
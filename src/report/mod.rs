@@ -0,0 +1,176 @@
+use crate::health::LinkHealth;
+use crate::{PoweneticsData, POWENETICS_MEASUREMENT_PACKET_SIZE};
+
+#[derive(Clone, Debug)]
+pub struct ChannelReport {
+    pub name: String,
+    /// Accumulated energy in nJ
+    pub energy: u64,
+    /// Estimated uncertainty of `energy` in nJ
+    pub energy_uncertainty: u64,
+}
+
+/// Summary of a measurement run.
+///
+/// The energy uncertainty of each channel is the sum of two terms:
+///
+/// * Quantization: voltage and current are rounded to the nearest LSB, so the power of each sample
+///   may be off by up to half an LSB of current times the voltage plus half an LSB of voltage
+///   times the current. This worst-case error is integrated over the run like the energy itself.
+/// * Timing jitter: each sample's energy is its power times the interval since the previous
+///   sample, as timestamped by the host. Assuming independent jitter with standard deviation σ
+///   around the mean interval μ, the relative error of the energy over n intervals is
+///   σ / (μ · √n).
+///
+/// The measurement quality is a score from 0 (untrustworthy) to 100 (no anomalies), starting at
+/// 100 and reduced by the following penalties, each capped:
+///
+/// * Dropped packets: 10 points per percent of packets dropped (at most 40)
+/// * Timing jitter: 5 points per 10% of the standard deviation relative to the mean interval
+///   (at most 20)
+/// * Backlog: 1 point per 10 packets waiting in the input buffer at its high water mark
+///   (at most 10)
+/// * Device resets: 10 points each (at most 20)
+/// * Implausible values in the final sample (see `PoweneticsData::validate`): 5 points each
+///   (at most 10)
+#[derive(Clone, Debug)]
+pub struct Report {
+    pub health: LinkHealth,
+    pub channels: Vec<ChannelReport>,
+    pub quality: u8,
+}
+
+impl Report {
+    pub(crate) fn new(data: &PoweneticsData, health: LinkHealth) -> Self {
+        let mean = health.mean_interval.as_secs_f64();
+        let relative_jitter = if health.intervals > 0 && mean > 0.0 {
+            health.interval_jitter.as_secs_f64() / (mean * (health.intervals as f64).sqrt())
+        } else {
+            0.0
+        };
+
+        let channels = data
+            .channels()
+            .iter()
+            .map(|ch| ChannelReport {
+                name: String::from(ch.name()),
+                energy: ch.energy(),
+                energy_uncertainty: ch.quantization_error
+                    + (ch.energy() as f64 * relative_jitter) as u64,
+            })
+            .collect();
+
+        Report {
+            health,
+            channels,
+            quality: quality(data, &health),
+        }
+    }
+
+    pub fn channel(&self, id: usize) -> Option<&ChannelReport> {
+        self.channels.get(id)
+    }
+}
+
+fn quality(data: &PoweneticsData, health: &LinkHealth) -> u8 {
+    let received = health.packets + health.dropped_packets;
+    let drop_rate = if received > 0 {
+        health.dropped_packets as f64 / received as f64
+    } else {
+        0.0
+    };
+
+    let mean = health.mean_interval.as_secs_f64();
+    let relative_jitter = if mean > 0.0 {
+        health.interval_jitter.as_secs_f64() / mean
+    } else {
+        0.0
+    };
+
+    let backlog_packets =
+        health.backlog_high_water as f64 / POWENETICS_MEASUREMENT_PACKET_SIZE as f64;
+
+    let penalty = (drop_rate * 1000.0).min(40.0)
+        + (relative_jitter * 50.0).min(20.0)
+        + (backlog_packets / 10.0).min(10.0)
+        + (health.device_resets as f64 * 10.0).min(20.0)
+        + (data.validate().len() as f64 * 5.0).min(10.0);
+
+    (100.0 - penalty).clamp(0.0, 100.0).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time;
+
+    use super::*;
+
+    /// 1% of packets dropped, jitter of 10% of the mean interval
+    fn health() -> LinkHealth {
+        LinkHealth {
+            packets: 990,
+            dropped_packets: 10,
+            mean_interval: time::Duration::from_millis(10),
+            interval_jitter: time::Duration::from_millis(1),
+            intervals: 100,
+            ..LinkHealth::default()
+        }
+    }
+
+    #[test]
+    fn uncertainty_adds_quantization_and_jitter() {
+        let mut data = PoweneticsData::new();
+        data.channels[0].energy = 1_000_000;
+        data.channels[0].quantization_error = 500;
+
+        let report = Report::new(&data, health());
+        let channel = report.channel(0).unwrap();
+
+        // σ / (μ · √n) = 1 ms / (10 ms · 10) = 1%
+        assert_eq!(channel.energy, 1_000_000);
+        assert_eq!(channel.energy_uncertainty, 500 + 10_000);
+        assert_eq!(report.channel(1).unwrap().energy_uncertainty, 0);
+    }
+
+    #[test]
+    fn quality_is_reduced_by_each_penalty() {
+        let data = PoweneticsData::new();
+        assert_eq!(quality(&data, &LinkHealth::default()), 100);
+
+        // 10 points for the drops and 5 for the jitter
+        assert_eq!(Report::new(&data, health()).quality, 85);
+
+        let health = LinkHealth {
+            backlog_high_water: 10 * POWENETICS_MEASUREMENT_PACKET_SIZE as u32,
+            device_resets: 1,
+            ..health()
+        };
+        assert_eq!(quality(&data, &health), 74);
+
+        let mut implausible = PoweneticsData::new();
+        implausible.channels[0].set_reading(0, 1_000);
+        assert_eq!(quality(&implausible, &health), 69);
+    }
+
+    #[test]
+    fn penalties_are_capped() {
+        let mut data = PoweneticsData::new();
+        for channel in &mut data.channels {
+            channel.set_reading(0, 1_000);
+        }
+
+        let health = LinkHealth {
+            packets: 100,
+            dropped_packets: 100,
+            mean_interval: time::Duration::from_millis(10),
+            interval_jitter: time::Duration::from_millis(100),
+            backlog_high_water: u32::MAX,
+            device_resets: 100,
+            ..LinkHealth::default()
+        };
+        assert_eq!(quality(&data, &health), 0);
+
+        // Without implausible values, 40 + 20 + 10 + 20 points are deducted
+        assert_eq!(quality(&PoweneticsData::new(), &health), 10);
+    }
+}
@@ -0,0 +1,241 @@
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crate::{ControlFlow, OwnedSample, PoweneticsData, PoweneticsSubscriber};
+
+/// Lock-free single-producer single-consumer ring of samples.
+///
+/// The buffer is allocated once with a fixed capacity, so pushing a sample never allocates. When
+/// the ring is full, new samples are dropped (the oldest samples are kept) and counted as
+/// overruns. The measurement loop never blocks on a slow consumer.
+struct Ring {
+    slots: Box<[UnsafeCell<OwnedSample>]>,
+    // Positions count modulo twice the capacity, which tells a full ring from an empty one while
+    // keeping the mapping to slots consistent when they wrap around.
+    // Position of the next slot to be read, only written by the consumer
+    head: AtomicUsize,
+    // Position of the next slot to be written, only written by the producer
+    tail: AtomicUsize,
+    overruns: AtomicU64,
+    producer_alive: AtomicBool,
+    consumer_alive: AtomicBool,
+}
+
+// Slots between head and tail are only accessed by the consumer, all others only by the producer.
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn len(&self) -> usize {
+        // Load head first so that a concurrent pop cannot make it overtake the loaded tail
+        let head = self.head.load(Ordering::Acquire);
+        self.distance(head, self.tail.load(Ordering::Acquire))
+    }
+
+    fn distance(&self, head: usize, tail: usize) -> usize {
+        (tail + 2 * self.slots.len() - head) % (2 * self.slots.len())
+    }
+
+    fn next(&self, position: usize) -> usize {
+        (position + 1) % (2 * self.slots.len())
+    }
+}
+
+/// Subscriber pushing a snapshot of every sample into the ring
+pub struct RingSink {
+    ring: Arc<Ring>,
+}
+
+/// Consumer side of the ring, may be moved to another thread
+pub struct RingConsumer {
+    ring: Arc<Ring>,
+}
+
+impl RingSink {
+    /// Create a ring able to hold `capacity` samples
+    pub fn new(capacity: usize) -> (RingSink, RingConsumer) {
+        assert!(capacity > 0, "ring capacity must not be zero");
+        assert!(capacity <= usize::MAX / 4, "ring capacity too large");
+
+        let ring = Arc::new(Ring {
+            slots: (0..capacity)
+                .map(|_| UnsafeCell::new(OwnedSample::default()))
+                .collect(),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            overruns: AtomicU64::new(0),
+            producer_alive: AtomicBool::new(true),
+            consumer_alive: AtomicBool::new(true),
+        });
+
+        (RingSink { ring: ring.clone() }, RingConsumer { ring })
+    }
+
+    fn push(&self, sample: OwnedSample) -> bool {
+        let ring = &self.ring;
+        let tail = ring.tail.load(Ordering::Relaxed);
+        let head = ring.head.load(Ordering::Acquire);
+
+        if ring.distance(head, tail) == ring.slots.len() {
+            ring.overruns.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        unsafe {
+            *ring.slots[tail % ring.slots.len()].get() = sample;
+        }
+
+        ring.tail.store(ring.next(tail), Ordering::Release);
+        true
+    }
+}
+
+impl PoweneticsSubscriber for RingSink {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        if !self.ring.consumer_alive.load(Ordering::Relaxed) {
+            return Ok(ControlFlow::Stop);
+        }
+
+        self.push(p.snapshot());
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+impl Drop for RingSink {
+    fn drop(&mut self) {
+        self.ring.producer_alive.store(false, Ordering::Release);
+    }
+}
+
+impl RingConsumer {
+    pub fn pop(&mut self) -> Option<OwnedSample> {
+        let ring = &self.ring;
+        let head = ring.head.load(Ordering::Relaxed);
+        let tail = ring.tail.load(Ordering::Acquire);
+
+        if head == tail {
+            return None;
+        }
+
+        let sample = unsafe { *ring.slots[head % ring.slots.len()].get() };
+
+        ring.head.store(ring.next(head), Ordering::Release);
+        Some(sample)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ring.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.slots.len()
+    }
+
+    /// Number of samples dropped because the ring was full
+    pub fn overruns(&self) -> u64 {
+        self.ring.overruns.load(Ordering::Relaxed)
+    }
+
+    /// Whether the sink has been dropped, i.e. no further samples will arrive once the ring is
+    /// empty
+    pub fn is_abandoned(&self) -> bool {
+        !self.ring.producer_alive.load(Ordering::Acquire)
+    }
+}
+
+impl Drop for RingConsumer {
+    fn drop(&mut self) {
+        self.ring.consumer_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    fn sample(index: u64) -> OwnedSample {
+        OwnedSample {
+            sample_index: index,
+            ..Default::default()
+        }
+    }
+
+    fn pop_all(consumer: &mut RingConsumer) -> Vec<u64> {
+        std::iter::from_fn(|| consumer.pop())
+            .map(|s| s.sample_index())
+            .collect()
+    }
+
+    #[test]
+    fn overruns_keep_the_oldest_samples() {
+        let (sink, mut consumer) = RingSink::new(3);
+
+        for i in 0..5 {
+            assert_eq!(sink.push(sample(i)), i < 3);
+        }
+
+        assert_eq!(consumer.len(), 3);
+        assert_eq!(consumer.overruns(), 2);
+        assert_eq!(pop_all(&mut consumer), [0, 1, 2]);
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn positions_wrap_around() {
+        let (sink, mut consumer) = RingSink::new(3);
+        let mut next = 0;
+
+        // Several times around the positions, with the ring partially and completely filled
+        for round in 0..20 {
+            let count = round % 5;
+            for i in next..next + count {
+                assert_eq!(sink.push(sample(i)), i < next + 3);
+            }
+
+            let expected: Vec<u64> = (next..next + count.min(3)).collect();
+            assert_eq!(consumer.len(), expected.len());
+            assert_eq!(pop_all(&mut consumer), expected);
+
+            next += count;
+        }
+
+        assert_eq!(consumer.overruns(), 4);
+    }
+
+    #[test]
+    fn samples_cross_threads_in_order() {
+        const SAMPLES: u64 = 100_000;
+        let (sink, mut consumer) = RingSink::new(64);
+
+        let producer = thread::spawn(move || {
+            for i in 0..SAMPLES {
+                while !sink.push(sample(i)) {
+                    thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < SAMPLES {
+            match consumer.pop() {
+                Some(sample) => {
+                    assert_eq!(sample.sample_index(), expected);
+                    expected += 1;
+                }
+                None if consumer.is_abandoned() && consumer.is_empty() => break,
+                None => thread::yield_now(),
+            }
+        }
+
+        producer.join().unwrap();
+        assert_eq!(expected, SAMPLES);
+        assert!(consumer.is_abandoned());
+    }
+}
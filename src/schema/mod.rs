@@ -0,0 +1,102 @@
+use crate::POWENETICS_CHANNELS;
+
+/// JSON Schema (draft 2020-12) of the JSON representation of a `PoweneticsData` sample.
+///
+/// A sample is an object holding its timestamp as (fractional) seconds since the Unix epoch, the
+/// annotations attached to it and all channels in protocol order. Each channel lists its default
+/// name, ID, voltage in mV, current in mA, energy in nJ and power in µW.
+pub fn data_json_schema() -> String {
+    let channels: Vec<String> = POWENETICS_CHANNELS
+        .iter()
+        .enumerate()
+        .map(|(id, name)| channel_schema(id, name))
+        .collect();
+
+    format!(
+        r#"{{
+  "$schema": "https://json-schema.org/draft/2020-12/schema",
+  "title": "Powenetics v2 sample",
+  "type": "object",
+  "properties": {{
+    "last_update": {{
+      "description": "Reception time in seconds since the Unix epoch",
+      "type": "number"
+    }},
+    "annotations": {{
+      "type": "array",
+      "items": {{ "type": "string" }}
+    }},
+    "channels": {{
+      "type": "array",
+      "prefixItems": [
+{}
+      ],
+      "items": false,
+      "minItems": {count},
+      "maxItems": {count}
+    }}
+  }},
+  "required": ["last_update", "annotations", "channels"]
+}}
+"#,
+        channels.join(",\n"),
+        count = POWENETICS_CHANNELS.len()
+    )
+}
+
+fn channel_schema(id: usize, name: &str) -> String {
+    format!(
+        r#"        {{
+          "title": "{name}",
+          "type": "object",
+          "properties": {{
+            "name": {{ "type": "string", "default": "{name}" }},
+            "id": {{ "const": {id} }},
+            "voltage": {{ "description": "mV", "type": "integer", "minimum": 0, "maximum": 65535 }},
+            "current": {{ "description": "mA", "type": "integer", "minimum": 0, "maximum": 16777215 }},
+            "energy": {{ "description": "nJ", "type": "integer", "minimum": 0 }},
+            "power": {{ "description": "µW", "type": "integer", "minimum": 0 }}
+          }},
+          "required": ["name", "id", "voltage", "current", "energy", "power"]
+        }}"#,
+        name = escape(name),
+    )
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_describes_all_channels_with_units() {
+        let schema: serde_json::Value = serde_json::from_str(&data_json_schema()).unwrap();
+        let channels = &schema["properties"]["channels"];
+        let items = channels["prefixItems"].as_array().unwrap();
+
+        assert_eq!(channels["minItems"], POWENETICS_CHANNELS.len());
+        assert_eq!(channels["maxItems"], POWENETICS_CHANNELS.len());
+        assert_eq!(items.len(), POWENETICS_CHANNELS.len());
+
+        for (id, (item, name)) in items.iter().zip(POWENETICS_CHANNELS).enumerate() {
+            let properties = &item["properties"];
+
+            assert_eq!(item["title"], name);
+            assert_eq!(properties["name"]["default"], name);
+            assert_eq!(properties["id"]["const"], id);
+
+            for (field, unit) in [
+                ("voltage", "mV"),
+                ("current", "mA"),
+                ("energy", "nJ"),
+                ("power", "µW"),
+            ] {
+                assert_eq!(properties[field]["description"], unit);
+                assert_eq!(properties[field]["type"], "integer");
+            }
+        }
+    }
+}
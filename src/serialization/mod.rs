@@ -0,0 +1,242 @@
+use std::{array, fmt, time};
+
+use serde::de::{self, Deserializer, IgnoredAny, MapAccess, Visitor};
+use serde::ser::{SerializeStruct, Serializer};
+use serde::{Deserialize, Serialize};
+
+use crate::{Channel, PoweneticsData, POWENETICS_CHANNELS};
+
+const CHANNEL_FIELDS: &[&str] = &["name", "id", "voltage", "current", "energy", "power"];
+const DATA_FIELDS: &[&str] = &["last_update", "annotations", "channels"];
+
+fn epoch_secs<E: serde::ser::Error>(t: time::SystemTime) -> Result<f64, E> {
+    t.duration_since(time::UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .map_err(|_| E::custom("timestamp before the Unix epoch"))
+}
+
+fn from_epoch_secs<E: de::Error>(secs: f64) -> Result<time::SystemTime, E> {
+    time::Duration::try_from_secs_f64(secs)
+        .map(|d| time::UNIX_EPOCH + d)
+        .map_err(E::custom)
+}
+
+fn missing<T, E: de::Error>(value: Option<T>, field: &'static str) -> Result<T, E> {
+    value.ok_or_else(|| E::missing_field(field))
+}
+
+impl Serialize for Channel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("Channel", CHANNEL_FIELDS.len())?;
+        s.serialize_field("name", &self.name)?;
+        s.serialize_field("id", &self.id)?;
+        s.serialize_field("voltage", &self.voltage)?;
+        s.serialize_field("current", &self.current)?;
+        s.serialize_field("energy", &self.energy)?;
+        s.serialize_field("power", &self.power)?;
+        s.end()
+    }
+}
+
+/// The power is recomputed from voltage and current rather than taken from the input. Secondary
+/// energy and the channel's timestamp are not part of the representation and start out reset.
+impl<'de> Deserialize<'de> for Channel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ChannelVisitor;
+
+        impl<'de> Visitor<'de> for ChannelVisitor {
+            type Value = Channel;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a Powenetics channel")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Channel, A::Error> {
+                let mut name = None;
+                let mut id = None;
+                let mut voltage = None;
+                let mut current = None;
+                let mut energy = None;
+
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "name" => name = Some(map.next_value::<String>()?),
+                        "id" => id = Some(map.next_value::<u8>()?),
+                        "voltage" => voltage = Some(map.next_value::<u16>()?),
+                        "current" => current = Some(map.next_value::<u32>()?),
+                        "energy" => energy = Some(map.next_value::<u64>()?),
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let id = missing(id, "id")?;
+                if id as usize >= POWENETICS_CHANNELS.len() {
+                    return Err(de::Error::custom(format!("invalid channel ID {}", id)));
+                }
+
+                let mut channel = Channel {
+                    name: missing(name, "name")?,
+                    id,
+                    voltage: 0,
+                    current: 0,
+                    power: 0,
+                    previous_power: 0,
+                    energy: missing(energy, "energy")?,
+                    energy_secondary: 0,
+                    quantization_error: 0,
+                    last_update: time::UNIX_EPOCH,
+                };
+                channel.set_reading(missing(voltage, "voltage")?, missing(current, "current")?);
+
+                Ok(channel)
+            }
+        }
+
+        deserializer.deserialize_struct("Channel", CHANNEL_FIELDS, ChannelVisitor)
+    }
+}
+
+impl Serialize for PoweneticsData {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_struct("PoweneticsData", DATA_FIELDS.len())?;
+        s.serialize_field("last_update", &epoch_secs::<S::Error>(self.last_update)?)?;
+        s.serialize_field("annotations", &self.annotations)?;
+        s.serialize_field("channels", &self.channels[..])?;
+        s.end()
+    }
+}
+
+/// Channels must be listed in protocol order. All channels take the sample's timestamp. Settings
+/// of the device the sample was taken from (excluded channels, virtual channels) are not part of
+/// the representation, so the totals of a deserialized sample include all channels. The sample
+/// index is not part of it either and is 0.
+impl<'de> Deserialize<'de> for PoweneticsData {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DataVisitor;
+
+        impl<'de> Visitor<'de> for DataVisitor {
+            type Value = PoweneticsData;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a Powenetics sample")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<PoweneticsData, A::Error> {
+                let mut last_update = None;
+                let mut annotations = None;
+                let mut channels = None;
+
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "last_update" => last_update = Some(map.next_value::<f64>()?),
+                        "annotations" => annotations = Some(map.next_value::<Vec<String>>()?),
+                        "channels" => channels = Some(map.next_value::<Vec<Channel>>()?),
+                        _ => {
+                            map.next_value::<IgnoredAny>()?;
+                        }
+                    }
+                }
+
+                let last_update = from_epoch_secs(missing(last_update, "last_update")?)?;
+                let mut channels = missing(channels, "channels")?;
+
+                if channels.len() != POWENETICS_CHANNELS.len() {
+                    return Err(de::Error::invalid_length(
+                        channels.len(),
+                        &"one entry per channel",
+                    ));
+                }
+
+                if let Some(i) = channels
+                    .iter()
+                    .enumerate()
+                    .position(|(i, c)| c.id as usize != i)
+                {
+                    return Err(de::Error::custom(format!(
+                        "channel at position {} has ID {}, channels must be in protocol order",
+                        i, channels[i].id
+                    )));
+                }
+
+                for channel in &mut channels {
+                    channel.last_update = last_update;
+                }
+
+                let mut channels = channels.into_iter();
+
+                Ok(PoweneticsData {
+                    channels: array::from_fn(|_| channels.next().unwrap()),
+                    last_update,
+                    annotations: missing(annotations, "annotations")?,
+                    excluded_from_totals: [false; POWENETICS_CHANNELS.len()],
+                    virtual_channels: vec![],
+                    off_energy: 0,
+                    sample_index: 0,
+                    elapsed: time::Duration::ZERO,
+                    sequence: 0,
+                    dropped_samples: 0,
+                })
+            }
+        }
+
+        deserializer.deserialize_struct("PoweneticsData", DATA_FIELDS, DataVisitor)
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use super::*;
+
+    fn sample() -> PoweneticsData {
+        let mut data = PoweneticsData::new();
+        // Exactly representable as seconds in an f64
+        data.last_update = time::UNIX_EPOCH + time::Duration::from_millis(1_700_000_000_500);
+        data.annotations.push(String::from("marker"));
+
+        for (i, channel) in data.channels.iter_mut().enumerate() {
+            channel.set_reading(12_000 + i as u16, 1_000 * i as u32);
+            channel.energy = 1_000_000 * i as u64;
+        }
+        data.channels[3].name = String::from("CPU");
+
+        data
+    }
+
+    #[test]
+    fn samples_round_trip() {
+        let data = sample();
+        let json = serde_json::to_string(&data).unwrap();
+        let parsed: PoweneticsData = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.snapshot(), data.snapshot());
+        assert_eq!(parsed.annotations(), data.annotations());
+        assert_eq!(parsed.channels().len(), POWENETICS_CHANNELS.len());
+        for (parsed, channel) in parsed.channels().iter().zip(data.channels()) {
+            assert_eq!(parsed.name(), channel.name());
+            assert_eq!(parsed.id(), channel.id());
+            assert_eq!(parsed.power(), channel.power());
+            assert_eq!(parsed.last_update, data.last_update);
+        }
+    }
+
+    #[test]
+    fn channels_out_of_order_are_rejected() {
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value["channels"].as_array_mut().unwrap().swap(0, 1);
+
+        let Err(err) = serde_json::from_value::<PoweneticsData>(value) else {
+            panic!("channels out of order accepted");
+        };
+        assert!(err.to_string().contains("protocol order"), "{}", err);
+    }
+
+    #[test]
+    fn missing_channels_are_rejected() {
+        let mut value = serde_json::to_value(sample()).unwrap();
+        value["channels"].as_array_mut().unwrap().pop();
+
+        assert!(serde_json::from_value::<PoweneticsData>(value).is_err());
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber, POWENETICS_CHANNELS};
+
+/// Minimum, maximum and mean of a quantity, all zero if no samples have been seen
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+}
+
+impl Summary {
+    fn update(&mut self, value: f64, samples: u64) {
+        if samples == 1 {
+            *self = Summary {
+                min: value,
+                max: value,
+                mean: value,
+            };
+            return;
+        }
+
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        // Incremental mean, does not accumulate a sum that could lose precision over long runs
+        self.mean += (value - self.mean) / samples as f64;
+    }
+}
+
+/// Statistics of a single channel
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ChannelStats {
+    /// Voltage in mV
+    pub voltage: Summary,
+    /// Current in mA
+    pub current: Summary,
+    /// Power in µW
+    pub power: Summary,
+}
+
+/// Statistics collected by a `StatsSubscriber`
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct StatsReport {
+    pub samples: u64,
+    /// Per channel, in protocol order
+    pub channels: [ChannelStats; POWENETICS_CHANNELS.len()],
+    /// Total power in µW, see `PoweneticsData::total_power`
+    pub total_power: Summary,
+}
+
+/// Tracks minimum, maximum and mean voltage, current and power of every channel, e.g. the peak
+/// and average power of a benchmark run. Wrap it in an `Arc<Mutex<_>>` to query it while
+/// subscribed.
+#[derive(Default)]
+pub struct StatsSubscriber {
+    report: StatsReport,
+}
+
+impl StatsSubscriber {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn snapshot(&self) -> StatsReport {
+        self.report
+    }
+
+    /// Discard all samples seen so far
+    pub fn reset(&mut self) {
+        self.report = StatsReport::default();
+    }
+}
+
+impl PoweneticsSubscriber for StatsSubscriber {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let report = &mut self.report;
+        report.samples += 1;
+
+        for (stats, channel) in report.channels.iter_mut().zip(p.channels()) {
+            stats
+                .voltage
+                .update(channel.voltage() as f64, report.samples);
+            stats
+                .current
+                .update(channel.current() as f64, report.samples);
+            stats.power.update(channel.power() as f64, report.samples);
+        }
+
+        report
+            .total_power
+            .update(p.total_power() as f64, report.samples);
+
+        Ok(ControlFlow::Continue)
+    }
+}
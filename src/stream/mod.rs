@@ -0,0 +1,223 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::{
+    ControlFlow, OwnedSample, Powenetics, PoweneticsData, PoweneticsError, PoweneticsSubscriber,
+    StopHandle,
+};
+
+#[derive(Default)]
+struct Queue {
+    samples: VecDeque<OwnedSample>,
+    error: Option<PoweneticsError>,
+    finished: bool,
+    waker: Option<Waker>,
+}
+
+#[derive(Clone, Default)]
+struct SharedQueue(Arc<Mutex<Queue>>);
+
+impl SharedQueue {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn push(&self, update: impl FnOnce(&mut Queue)) {
+        let waker = {
+            let mut queue = self.lock();
+            update(&mut queue);
+            queue.waker.take()
+        };
+
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl PoweneticsSubscriber for SharedQueue {
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let sample = p.snapshot();
+        self.push(|queue| queue.samples.push_back(sample));
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+/// Samples of a measurement running on a background thread, to be awaited from async code.
+///
+/// This does not depend on a particular async runtime: the measurement thread wakes the
+/// awaiting task whenever a sample arrives. Samples queue up without bound if they are not
+/// awaited fast enough. If the measurement fails, the error is yielded after the samples
+/// received before it, and the stream ends. Dropping the stream stops the measurement.
+pub struct SampleStream {
+    queue: SharedQueue,
+    stop: StopHandle,
+    handle: Option<thread::JoinHandle<Powenetics>>,
+}
+
+impl SampleStream {
+    /// Wait for the next sample, `None` once the measurement has ended
+    pub fn next_sample(&mut self) -> NextSample<'_> {
+        NextSample(self)
+    }
+
+    /// Poll for the next sample, e.g. to implement `futures::Stream` on top of this
+    pub fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<OwnedSample, PoweneticsError>>> {
+        let mut queue = self.queue.lock();
+
+        if let Some(sample) = queue.samples.pop_front() {
+            return Poll::Ready(Some(Ok(sample)));
+        }
+
+        if let Some(err) = queue.error.take() {
+            return Poll::Ready(Some(Err(err)));
+        }
+
+        if queue.finished {
+            return Poll::Ready(None);
+        }
+
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    /// Request the measurement to stop, see `StopHandle::stop`. Samples already queued are
+    /// still yielded.
+    pub fn stop(&self) {
+        self.stop.stop();
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    /// Wait for the measurement to end and return the `Powenetics`, e.g. for its `report`. This
+    /// blocks, so call it once `next_sample` returned `None` or after stopping the measurement.
+    /// Panics of subscribers are propagated.
+    pub fn into_inner(mut self) -> Powenetics {
+        let handle = self
+            .handle
+            .take()
+            .expect("measurement thread already joined");
+
+        match handle.join() {
+            Ok(powenetics) => powenetics,
+            Err(panic) => std::panic::resume_unwind(panic),
+        }
+    }
+}
+
+impl Drop for SampleStream {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            self.stop.stop();
+        }
+    }
+}
+
+/// Future returned by `SampleStream::next_sample`
+pub struct NextSample<'a>(&'a mut SampleStream);
+
+impl Future for NextSample<'_> {
+    type Output = Option<Result<OwnedSample, PoweneticsError>>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        self.0.poll_next(cx)
+    }
+}
+
+impl Powenetics {
+    /// Start the measurement on a background thread and stream its samples to async code, see
+    /// `SampleStream`. Subscribers registered beforehand are called on the background thread as
+    /// well, and may stop the measurement, which ends the stream.
+    pub fn into_sample_stream(mut self) -> Result<SampleStream, PoweneticsError> {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        let queue = SharedQueue::default();
+        let stop = self.stop_handle();
+        let id = self.subscribe(Box::new(queue.clone()));
+
+        let thread_queue = queue.clone();
+        let handle = thread::Builder::new()
+            .name(String::from("powenetics"))
+            .spawn(move || {
+                let result = self.start_measurement();
+                self.unsubscribe(id);
+
+                thread_queue.push(|queue| {
+                    queue.error = result.err();
+                    queue.finished = true;
+                });
+
+                self
+            })?;
+
+        Ok(SampleStream {
+            queue,
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::task::Wake;
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+
+    /// Unparks the polling thread, enough of an executor to drive `next_sample`
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+        let mut cx = Context::from_waker(&waker);
+        let mut future = std::pin::pin!(future);
+
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn samples_are_streamed_until_the_measurement_ends() {
+        let port = MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let mut stream = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(3)))
+            .into_sample_stream()
+            .unwrap();
+
+        let mut indices = vec![];
+        while let Some(sample) = block_on(stream.next_sample()) {
+            indices.push(sample.unwrap().sample_index());
+        }
+
+        assert_eq!(indices, [0, 1, 2]);
+        assert_eq!(
+            stream.into_inner().stop_reason(),
+            Some(crate::StopReason::Subscriber)
+        );
+    }
+}
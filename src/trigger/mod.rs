@@ -0,0 +1,214 @@
+use std::collections::VecDeque;
+use std::time;
+
+use crate::metadata::RunMetadata;
+use crate::{ControlFlow, OwnedSample, PoweneticsData, PoweneticsSubscriber, StopReason};
+
+/// Forwards samples to the inner subscriber only around events where total power rises above a
+/// threshold, like the trigger of an oscilloscope.
+///
+/// While waiting for the trigger, the samples of the last `pre_trigger` duration are buffered.
+/// Once total power exceeds the threshold, the buffered samples are forwarded first, followed by
+/// all samples until total power has stayed at or below the threshold for `post_trigger`. The
+/// trigger then re-arms. Both durations are zero by default.
+///
+/// Buffered samples are kept as `OwnedSample`s, so buffering does not allocate per sample. They
+/// are forwarded with their timestamps, sample indices and readings as received, so their energy
+/// does not include the energy of later samples; all other fields are those of the sample that
+/// fired the trigger. For buffered samples, only `ControlFlow::Stop` is honored; other actions are
+/// honored from the sample that fired the trigger on.
+pub struct TriggerSubscriber<S: PoweneticsSubscriber> {
+    inner: S,
+    threshold: u64,
+    pre_trigger: time::Duration,
+    post_trigger: time::Duration,
+    buffer: VecDeque<OwnedSample>,
+    triggered: bool,
+    below_since: Option<time::SystemTime>,
+    events: u64,
+}
+
+impl<S: PoweneticsSubscriber> TriggerSubscriber<S> {
+    pub fn new(inner: S, threshold_watts: f64) -> Self {
+        TriggerSubscriber {
+            inner,
+            threshold: (threshold_watts * 1_000_000.0) as u64,
+            pre_trigger: time::Duration::ZERO,
+            post_trigger: time::Duration::ZERO,
+            buffer: VecDeque::new(),
+            triggered: false,
+            below_since: None,
+            events: 0,
+        }
+    }
+
+    /// Duration of samples before the trigger to be forwarded as well
+    pub fn pre_trigger(mut self, duration: time::Duration) -> Self {
+        self.pre_trigger = duration;
+        self
+    }
+
+    /// Duration total power must stay at or below the threshold to end a triggered window
+    pub fn post_trigger(mut self, duration: time::Duration) -> Self {
+        self.post_trigger = duration;
+        self
+    }
+
+    /// Whether samples are currently being forwarded
+    pub fn triggered(&self) -> bool {
+        self.triggered
+    }
+
+    /// Number of times the trigger fired
+    pub fn events(&self) -> u64 {
+        self.events
+    }
+
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+
+    fn buffer(&mut self, p: &PoweneticsData) {
+        if self.pre_trigger.is_zero() {
+            return;
+        }
+
+        while let Some(oldest) = self.buffer.front() {
+            match p.last_update().duration_since(oldest.last_update()) {
+                Ok(age) if age <= self.pre_trigger => break,
+                _ => self.buffer.pop_front(),
+            };
+        }
+
+        self.buffer.push_back(p.snapshot());
+    }
+}
+
+impl<S: PoweneticsSubscriber> PoweneticsSubscriber for TriggerSubscriber<S> {
+    fn begin(&mut self, metadata: &RunMetadata, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.buffer.clear();
+        self.triggered = false;
+        self.below_since = None;
+        self.inner.begin(metadata, p)
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let above = p.total_power() > self.threshold;
+
+        if !self.triggered {
+            if !above {
+                self.buffer(p);
+                return Ok(ControlFlow::Continue);
+            }
+
+            self.triggered = true;
+            self.events += 1;
+
+            let mut buffered = p.clone();
+            buffered.annotations.clear();
+
+            while let Some(sample) = self.buffer.pop_front() {
+                buffered.apply(&sample);
+
+                if self.inner.update(&buffered)? == ControlFlow::Stop {
+                    self.buffer.clear();
+                    return Ok(ControlFlow::Stop);
+                }
+            }
+        } else if above {
+            self.below_since = None;
+        } else {
+            let below_since = *self.below_since.get_or_insert(p.last_update());
+
+            if p.last_update()
+                .duration_since(below_since)
+                .unwrap_or_default()
+                >= self.post_trigger
+            {
+                self.triggered = false;
+                self.below_since = None;
+            }
+        }
+
+        self.inner.update(p)
+    }
+
+    fn end(&mut self, reason: StopReason, p: &PoweneticsData) -> anyhow::Result<()> {
+        self.inner.end(reason, p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+
+    #[derive(Default)]
+    struct Forwarded(Vec<(u64, u64)>);
+
+    impl PoweneticsSubscriber for Forwarded {
+        fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+            self.0.push((p.sample_index(), p.total_power()));
+            Ok(ControlFlow::Continue)
+        }
+    }
+
+    #[test]
+    fn spike_forwards_pre_and_post_trigger_samples() {
+        // 12 V at 0.1 A (1.2 W) or 1 A (12 W) on each channel
+        let (idle, spike) = ([100; 13], [1_000; 13]);
+        let mut port = MockSerialPort::new();
+        for current in [idle; 5].into_iter().chain([spike; 2]).chain([idle; 5]) {
+            port = port.packet([12_000; 13], current);
+        }
+
+        let trigger = Arc::new(Mutex::new(
+            TriggerSubscriber::new(Forwarded::default(), 100.0)
+                .pre_trigger(time::Duration::from_secs(60))
+                .post_trigger(time::Duration::ZERO),
+        ));
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(trigger.clone()))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(12)));
+        p.start_measurement().unwrap();
+
+        let trigger = trigger.lock().unwrap();
+        let forwarded = &trigger.inner.0;
+        let indices: Vec<u64> = forwarded.iter().map(|&(index, _)| index).collect();
+        let idle_power = 13 * 12_000 * 100;
+        let spike_power = 13 * 12_000 * 1_000;
+
+        assert_eq!(trigger.events(), 1);
+        assert!(!trigger.triggered());
+        // All 5 pre-trigger samples, the spike and the first sample back at or below threshold
+        assert_eq!(indices, (0..8).collect::<Vec<_>>());
+        assert!(forwarded[..5].iter().all(|&(_, power)| power == idle_power));
+        assert!(forwarded[5..7]
+            .iter()
+            .all(|&(_, power)| power == spike_power));
+        assert_eq!(forwarded[7].1, idle_power);
+    }
+
+    #[test]
+    fn pre_trigger_buffer_drops_old_samples() {
+        let port = MockSerialPort::new()
+            .packets(3, [12_000; 13], [100; 13])
+            .packet([12_000; 13], [1_000; 13]);
+        let trigger = Arc::new(Mutex::new(TriggerSubscriber::new(
+            Forwarded::default(),
+            100.0,
+        )));
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber(Box::new(trigger.clone()))
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(4)));
+        p.start_measurement().unwrap();
+
+        // Without a pre-trigger duration, only the spike is forwarded
+        let trigger = trigger.lock().unwrap();
+        assert_eq!(trigger.inner.0.len(), 1);
+        assert_eq!(trigger.inner.0[0].0, 3);
+    }
+}
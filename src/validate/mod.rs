@@ -0,0 +1,132 @@
+use std::{fmt, time};
+
+use crate::{ChannelId, PoweneticsData};
+
+/// Deviation from the nominal voltage beyond which a reading is considered implausible. ATX
+/// allows ±5%, the margin also covers measurement error and load transients.
+const VOLTAGE_TOLERANCE: f64 = 0.10;
+
+/// Physically implausible observation in a sample, see `PoweneticsData::validate`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationIssue {
+    /// Voltage (in mV) too far from the channel's nominal voltage. Channels reading 0 mV are
+    /// considered unconnected rather than implausible.
+    VoltageOutOfRange { channel: ChannelId, voltage: u16 },
+    /// Current drawn from a channel reporting no voltage
+    CurrentWithoutVoltage { channel: ChannelId, current: u32 },
+    /// Channel updated after the sample was received
+    ChannelTimestampAfterSample { channel: ChannelId },
+    /// Sample received in the future according to the system clock
+    TimestampInFuture,
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::VoltageOutOfRange { channel, voltage } => write!(
+                f,
+                "{}: {} mV is too far from the nominal {} mV",
+                channel.name(),
+                voltage,
+                channel.nominal_voltage()
+            ),
+            ValidationIssue::CurrentWithoutVoltage { channel, current } => {
+                write!(f, "{}: {} mA drawn at 0 mV", channel.name(), current)
+            }
+            ValidationIssue::ChannelTimestampAfterSample { channel } => {
+                write!(
+                    f,
+                    "{}: updated after the sample was received",
+                    channel.name()
+                )
+            }
+            ValidationIssue::TimestampInFuture => write!(f, "sample received in the future"),
+        }
+    }
+}
+
+impl PoweneticsData {
+    /// Check the sample for physical plausibility, returning all issues found (none if the sample
+    /// is plausible). Energy cannot be negative by construction and is not checked.
+    pub fn validate(&self) -> Vec<ValidationIssue> {
+        let mut issues = vec![];
+
+        for channel in &self.channels {
+            let id = channel.channel_id();
+
+            if channel.voltage == 0 {
+                if channel.current != 0 {
+                    issues.push(ValidationIssue::CurrentWithoutVoltage {
+                        channel: id,
+                        current: channel.current,
+                    });
+                }
+            } else {
+                let nominal = id.nominal_voltage() as f64;
+
+                if (channel.voltage as f64 - nominal).abs() > nominal * VOLTAGE_TOLERANCE {
+                    issues.push(ValidationIssue::VoltageOutOfRange {
+                        channel: id,
+                        voltage: channel.voltage,
+                    });
+                }
+            }
+
+            if channel.last_update > self.last_update {
+                issues.push(ValidationIssue::ChannelTimestampAfterSample { channel: id });
+            }
+        }
+
+        if self.last_update > time::SystemTime::now() {
+            issues.push(ValidationIssue::TimestampInFuture);
+        }
+
+        issues
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sample with every channel at its nominal voltage drawing 1 A
+    fn nominal() -> PoweneticsData {
+        let mut data = PoweneticsData::new();
+
+        for (channel, id) in data.channels.iter_mut().zip(ChannelId::ALL) {
+            channel.set_reading(id.nominal_voltage(), 1_000);
+        }
+
+        data
+    }
+
+    #[test]
+    fn nominal_samples_are_plausible() {
+        assert_eq!(nominal().validate(), []);
+    }
+
+    #[test]
+    fn implausible_readings_are_reported() {
+        let mut data = nominal();
+        data.channels[ChannelId::Atx3V3 as usize].set_reading(2_900, 1_000);
+        // Right at the tolerance
+        data.channels[ChannelId::Atx5V as usize].set_reading(5_500, 1_000);
+        data.channels[ChannelId::Atx12V as usize].set_reading(0, 500);
+        // Unconnected
+        data.channels[ChannelId::PcieSlot3V3 as usize].set_reading(0, 0);
+
+        assert_eq!(
+            data.validate(),
+            [
+                ValidationIssue::VoltageOutOfRange {
+                    channel: ChannelId::Atx3V3,
+                    voltage: 2_900
+                },
+                ValidationIssue::CurrentWithoutVoltage {
+                    channel: ChannelId::Atx12V,
+                    current: 500
+                },
+            ]
+        );
+    }
+}
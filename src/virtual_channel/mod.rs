@@ -0,0 +1,130 @@
+use std::time;
+
+use crate::{IntegrationMethod, PoweneticsData};
+
+pub(crate) type VirtualChannelFn = Box<dyn FnMut(&PoweneticsData) -> (u16, u32) + Send>;
+
+/// Channel computed from the measured channels, see `Powenetics::add_virtual_channel`.
+///
+/// Energy is integrated from the computed power over the interval between samples, the same way as
+/// for measured channels. Virtual channels are not included in totals.
+#[derive(Clone)]
+pub struct VirtualChannel {
+    name: String,
+    pub(crate) voltage: u16,
+    pub(crate) current: u32,
+    pub(crate) energy: u64,
+    pub(crate) last_update: time::SystemTime,
+    previous_power: u64,
+}
+
+impl VirtualChannel {
+    pub(crate) fn new(name: &str) -> Self {
+        VirtualChannel {
+            name: String::from(name),
+            voltage: 0,
+            current: 0,
+            energy: 0,
+            last_update: time::SystemTime::UNIX_EPOCH,
+            previous_power: 0,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Voltage in mV
+    pub fn voltage(&self) -> u16 {
+        self.voltage
+    }
+
+    /// Current in mA
+    pub fn current(&self) -> u32 {
+        self.current
+    }
+
+    /// Power in µW, wider than `Channel::power` as currents of several channels may be combined
+    pub fn power(&self) -> u64 {
+        self.voltage as u64 * self.current as u64
+    }
+
+    /// Energy in nJ
+    pub fn energy(&self) -> u64 {
+        self.energy
+    }
+
+    pub(crate) fn update(
+        &mut self,
+        voltage: u16,
+        current: u32,
+        time: time::SystemTime,
+        integrate: bool,
+        method: IntegrationMethod,
+    ) {
+        self.previous_power = self.power();
+        self.voltage = voltage;
+        self.current = current;
+
+        if integrate && self.last_update != time::SystemTime::UNIX_EPOCH {
+            // Zero if the system clock stepped backwards
+            let duration = time.duration_since(self.last_update).unwrap_or_default();
+            let power = method.interval_power(self.previous_power, self.power());
+            self.energy += power * (duration.as_micros() as u64) / 1000;
+        }
+
+        self.last_update = time;
+    }
+
+    pub fn reset_energy(&mut self) {
+        self.energy = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::limit::SampleCountLimitSubscriber;
+    use crate::mock::MockSerialPort;
+    use crate::{ChannelId, ControlFlow};
+
+    #[test]
+    fn sum_of_channels_matches_its_sources() {
+        let mut currents = [0; 13];
+        currents[ChannelId::Atx12V as usize] = 2_000;
+        currents[ChannelId::Eps12V1 as usize] = 1_500;
+
+        let port = MockSerialPort::new().packets(10, [12_000; 13], currents);
+        let mut p = crate::from_port(Box::new(port), "mock")
+            .with_subscriber_fn(|_| {
+                // Energy is integrated in whole µs
+                thread::sleep(time::Duration::from_millis(1));
+                Ok(ControlFlow::Continue)
+            })
+            .with_subscriber(Box::new(SampleCountLimitSubscriber::new(10)));
+
+        let sources = [ChannelId::Atx12V, ChannelId::Eps12V1];
+        p.add_virtual_channel("12V", move |p| {
+            let channels = sources.map(|id| p.channel(id));
+            (
+                channels[0].voltage(),
+                channels.iter().map(|ch| ch.current()).sum(),
+            )
+        });
+        p.start_measurement().unwrap();
+
+        let data = p.data();
+        let channel = &data.virtual_channels()[0];
+        let energy: u64 = sources.iter().map(|&id| data.channel(id).energy()).sum();
+
+        assert_eq!(channel.name(), "12V");
+        assert_eq!(channel.voltage(), 12_000);
+        assert_eq!(channel.current(), 3_500);
+        assert_eq!(channel.power(), 12_000 * 3_500);
+        assert!(energy > 0);
+        // Each source's interval energy is rounded down separately
+        assert!(channel.energy() >= energy && channel.energy() - energy <= 10);
+    }
+}
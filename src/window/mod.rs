@@ -0,0 +1,168 @@
+use std::collections::VecDeque;
+use std::time;
+
+use crate::metadata::RunMetadata;
+use crate::{ControlFlow, PoweneticsData, PoweneticsSubscriber, POWENETICS_CHANNELS};
+
+/// Keeps the cumulative channel energies of the recent past to compute the average power over a
+/// time window.
+///
+/// The average is derived from the energy difference between the start and the end of the window,
+/// so querying it is cheap regardless of the number of samples within the window. Wrap it in an
+/// `Arc<Mutex<_>>` to query it while subscribed. The window is reset when a measurement begins.
+pub struct PowerWindow {
+    max_window: time::Duration,
+    samples: VecDeque<(time::SystemTime, [u64; POWENETICS_CHANNELS.len()])>,
+}
+
+impl PowerWindow {
+    /// Create a window supporting averages over up to `max_window`
+    pub fn new(max_window: time::Duration) -> Self {
+        PowerWindow {
+            max_window,
+            samples: VecDeque::new(),
+        }
+    }
+
+    fn start_index(&self, window: time::Duration) -> Option<usize> {
+        let (end, _) = self.samples.back()?;
+        let start = end.checked_sub(window)?;
+
+        // Use the latest sample at or before the start of the window, or the oldest one if the
+        // window is not covered completely yet
+        Some(
+            self.samples
+                .partition_point(|(time, _)| *time <= start)
+                .saturating_sub(1),
+        )
+    }
+
+    /// Time span actually covered by `average_power_over(window)`, shorter than `window` until
+    /// enough samples have been collected
+    pub fn coverage(&self, window: time::Duration) -> time::Duration {
+        let Some(start) = self.start_index(window) else {
+            return time::Duration::ZERO;
+        };
+
+        let (end, _) = self.samples.back().unwrap();
+        end.duration_since(self.samples[start].0)
+            .unwrap_or_default()
+    }
+
+    /// Average power per channel in µW over the last `window` (limited to the maximum window),
+    /// or `None` if less than two samples have been collected
+    pub fn average_power_over(
+        &self,
+        window: time::Duration,
+    ) -> Option<[u64; POWENETICS_CHANNELS.len()]> {
+        let start = self.start_index(window.min(self.max_window))?;
+        let (start_time, start_energies) = &self.samples[start];
+        let (end_time, end_energies) = self.samples.back()?;
+        let elapsed = end_time.duration_since(*start_time).ok()?.as_micros() as u64;
+
+        if elapsed == 0 {
+            return None;
+        }
+
+        let mut power = [0; POWENETICS_CHANNELS.len()];
+        for (i, p) in power.iter_mut().enumerate() {
+            // nJ / µs = mW
+            *p = end_energies[i].saturating_sub(start_energies[i]) * 1000 / elapsed;
+        }
+
+        Some(power)
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+    }
+}
+
+impl PoweneticsSubscriber for PowerWindow {
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.reset();
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        let sample = p.snapshot();
+        self.samples
+            .push_back((sample.last_update(), *sample.energies()));
+
+        if let Some(start) = p.last_update().checked_sub(self.max_window) {
+            // Keep one sample at or before the start of the maximum window
+            while self.samples.len() > 1 && self.samples[1].0 <= start {
+                self.samples.pop_front();
+            }
+        }
+
+        Ok(ControlFlow::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed one sample per second for `secs` seconds, with channel 0 drawing `watts(t)` during
+    /// second `t`
+    fn feed(window: &mut PowerWindow, secs: u64, watts: impl Fn(u64) -> u64) {
+        let mut data = PoweneticsData::new();
+
+        for t in 0..=secs {
+            if t != 0 {
+                data.channels[0].energy += watts(t - 1) * 1_000_000_000;
+            }
+
+            data.last_update = time::UNIX_EPOCH + time::Duration::from_secs(t);
+            window.update(&data).unwrap();
+        }
+    }
+
+    #[test]
+    fn average_power_matches_the_analytic_average() {
+        let mut window = PowerWindow::new(time::Duration::from_secs(8));
+        assert_eq!(
+            window.average_power_over(time::Duration::from_secs(4)),
+            None
+        );
+
+        // 2 W for five seconds, then 6 W
+        feed(&mut window, 10, |t| if t < 5 { 2 } else { 6 });
+
+        let average = |secs| {
+            window
+                .average_power_over(time::Duration::from_secs(secs))
+                .unwrap()[0]
+        };
+        assert_eq!(average(4), 6_000_000);
+        // (3 s * 2 W + 5 s * 6 W) / 8 s
+        assert_eq!(average(8), 4_500_000);
+        // Limited to the maximum window
+        assert_eq!(average(20), 4_500_000);
+        assert_eq!(
+            window.coverage(time::Duration::from_secs(8)),
+            time::Duration::from_secs(8)
+        );
+    }
+
+    #[test]
+    fn begin_resets_the_window() {
+        let mut window = PowerWindow::new(time::Duration::from_secs(60));
+        feed(&mut window, 3, |_| 1);
+
+        let data = PoweneticsData::new();
+        window
+            .begin(&RunMetadata::new(None, "mock", &[]), &data)
+            .unwrap();
+
+        assert_eq!(
+            window.coverage(time::Duration::from_secs(60)),
+            time::Duration::ZERO
+        );
+        assert_eq!(
+            window.average_power_over(time::Duration::from_secs(60)),
+            None
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::csv_row::CsvRowFormat;
+use crate::metadata::RunMetadata;
+use crate::{
+    ControlFlow, Powenetics, PoweneticsData, PoweneticsError, PoweneticsSubscriber, StopReason,
+};
+
+/// Text format of the samples written by a `WriterSubscriber`
+#[derive(Copy, Clone, Debug)]
+pub enum SampleFormat {
+    /// Delimited text with a header line, see `CsvRowFormat`
+    Csv(CsvRowFormat),
+}
+
+impl Default for SampleFormat {
+    fn default() -> Self {
+        SampleFormat::Csv(CsvRowFormat::default())
+    }
+}
+
+/// Writes every sample to an `io::Write` sink, optionally stopping after a number of samples.
+///
+/// Each measurement starts with a header, written along with its first sample, and the limit
+/// applies to every measurement. The sink is flushed when the measurement stops cleanly; wrap it
+/// in a `BufWriter` where writes are expensive.
+pub struct WriterSubscriber<W: Write + Send> {
+    writer: W,
+    format: SampleFormat,
+    limit: Option<u64>,
+    written: u64,
+    buf: String,
+}
+
+impl<W: Write + Send> WriterSubscriber<W> {
+    pub fn new(writer: W, format: SampleFormat) -> Self {
+        WriterSubscriber {
+            writer,
+            format,
+            limit: None,
+            written: 0,
+            buf: String::new(),
+        }
+    }
+
+    /// Stop the measurement once `samples` samples have been written
+    pub fn limit(mut self, samples: u64) -> Self {
+        self.limit = Some(samples);
+        self
+    }
+
+    /// Number of samples written in the current (or last) measurement
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write + Send> PoweneticsSubscriber for WriterSubscriber<W> {
+    fn begin(&mut self, _metadata: &RunMetadata, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.written = 0;
+        Ok(())
+    }
+
+    fn update(&mut self, p: &PoweneticsData) -> anyhow::Result<ControlFlow> {
+        if self.limit.is_some_and(|limit| self.written >= limit) {
+            return Ok(ControlFlow::Stop);
+        }
+
+        self.buf.clear();
+
+        match &self.format {
+            SampleFormat::Csv(format) => {
+                if self.written == 0 {
+                    format.header(p, &mut self.buf);
+                }
+
+                format.row(p, &mut self.buf);
+            }
+        }
+
+        self.writer.write_all(self.buf.as_bytes())?;
+        self.written += 1;
+
+        Ok(self.limit.is_some_and(|limit| self.written >= limit).into())
+    }
+
+    fn end(&mut self, _reason: StopReason, _p: &PoweneticsData) -> anyhow::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Powenetics {
+    /// Run a measurement writing every sample to `writer` until it is stopped, either by another
+    /// subscriber, a stop handle or after `limit` samples, and return the writer.
+    ///
+    /// Subscribers registered beforehand keep being called, before the writer. The writer is
+    /// unsubscribed again when the measurement ends.
+    pub fn sample_stream_to_writer<W>(
+        &mut self,
+        writer: W,
+        format: SampleFormat,
+        limit: Option<u64>,
+    ) -> Result<W, PoweneticsError>
+    where
+        W: Write + Send + 'static,
+    {
+        if self.started {
+            return Err(PoweneticsError::MeasurementAlreadyStarted);
+        }
+
+        let mut sink = WriterSubscriber::new(writer, format);
+        sink.limit = limit;
+
+        let sink = Arc::new(Mutex::new(sink));
+
+        // Subscribers of equal priority are called in order of subscription
+        let id = self.subscribe_with_priority(Box::new(sink.clone()), i32::MIN);
+
+        let result = self.start_measurement();
+        self.unsubscribe(id);
+        result?;
+
+        let sink = match Arc::try_unwrap(sink) {
+            Ok(sink) => sink,
+            Err(_) => unreachable!("the writer subscription has been dropped"),
+        };
+
+        Ok(sink
+            .into_inner()
+            .unwrap_or_else(|err| err.into_inner())
+            .into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockSerialPort;
+
+    fn lines(buf: &[u8]) -> Vec<&str> {
+        std::str::from_utf8(buf).unwrap().lines().collect()
+    }
+
+    #[test]
+    fn stream_writes_a_header_and_the_bounded_samples() {
+        let port = MockSerialPort::new().packets(5, [12_000; 13], [1_000; 13]);
+        let mut p = crate::from_port(Box::new(port), "mock");
+
+        let buf = p
+            .sample_stream_to_writer(Vec::new(), SampleFormat::default(), Some(3))
+            .unwrap();
+        let lines = lines(&buf);
+
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("Timestamp,"));
+        assert!(lines[1..].iter().all(|line| line.contains(",12000,1000,")));
+        assert_eq!(p.stop_reason(), Some(StopReason::Subscriber));
+        // The writer is unsubscribed again
+        assert!(p.subscriptions.is_empty());
+    }
+
+    #[test]
+    fn restarts_begin_with_a_header() {
+        let port = MockSerialPort::new()
+            .packets(2, [12_000; 13], [1_000; 13])
+            .pause()
+            .packets(2, [12_000; 13], [1_000; 13]);
+        let sink = Arc::new(Mutex::new(
+            WriterSubscriber::new(Vec::new(), SampleFormat::default()).limit(2),
+        ));
+        let mut p =
+            crate::from_port(Box::new(port), "mock").with_subscriber(Box::new(sink.clone()));
+
+        p.start_measurement().unwrap();
+        p.start_measurement().unwrap();
+
+        let sink = sink.lock().unwrap();
+        let lines = lines(&sink.writer);
+        assert_eq!(sink.written(), 2);
+        assert_eq!(lines.len(), 6);
+        assert!(lines[0].starts_with("Timestamp,"));
+        assert!(lines[3].starts_with("Timestamp,"));
+    }
+}